@@ -0,0 +1,102 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use jl2js::{Options, OutputSink};
+use std::fs::{self, File};
+use std::hint::black_box;
+use std::io::{self, Cursor};
+
+/// Lines of a typical event log.
+fn input(lines: usize) -> String {
+    (0..lines)
+        .map(|i| {
+            format!(
+                "{{\"id\":{},\"user\":{{\"name\":\"user{}\",\"tags\":[\"a\",\"b\"]}},\"score\":{}.5,\"ok\":true}}\n",
+                i,
+                i % 100,
+                i
+            )
+        })
+        .collect()
+}
+
+fn bench_convert(c: &mut Criterion) {
+    let input = input(10_000);
+    let mut group = c.benchmark_group("convert");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+
+    for passthrough in [false, true] {
+        let options = Options {
+            passthrough,
+            ..Options::default()
+        };
+        let name = if passthrough { "passthrough" } else { "values" };
+
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| jl2js::convert(Cursor::new(black_box(input)), io::sink(), &options).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+/// Reads a file of large records with input buffers of several sizes.
+fn bench_read_buffer(c: &mut Criterion) {
+    let big: String = (0..2_000)
+        .map(|i| format!("{{\"id\":{},\"payload\":\"{}\"}}\n", i, "x".repeat(4096)))
+        .collect();
+    let path = std::env::temp_dir().join(format!("jl2js-bench-{}.jsonl", std::process::id()));
+    fs::write(&path, &big).unwrap();
+
+    let mut group = c.benchmark_group("read_buffer");
+    group.throughput(Throughput::Bytes(big.len() as u64));
+
+    for size in [8 * 1024, 64 * 1024, 256 * 1024] {
+        let options = Options {
+            passthrough: true,
+            read_buffer_size: Some(size),
+            ..Options::default()
+        };
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), &path, |b, path| {
+            b.iter(|| jl2js::convert(File::open(path).unwrap(), io::sink(), &options).unwrap())
+        });
+    }
+
+    group.finish();
+    fs::remove_file(path).unwrap();
+}
+
+/// Writes gzip output with compression on the converting thread and on a
+/// thread of its own.
+fn bench_gzip_output(c: &mut Criterion) {
+    let input = input(50_000);
+    let path = std::env::temp_dir().join(format!("jl2js-bench-{}.json.gz", std::process::id()));
+
+    let mut group = c.benchmark_group("gzip_output");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+
+    for (name, background) in [("inline", false), ("background", true)] {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| {
+                let mut sink = OutputSink::from_file(path.clone()).unwrap().gzip();
+
+                if background {
+                    sink = sink.in_background();
+                }
+
+                jl2js::convert(
+                    Cursor::new(black_box(input)),
+                    &mut sink,
+                    &Options::default(),
+                )
+                .unwrap();
+                sink.finish().unwrap();
+            })
+        });
+    }
+
+    group.finish();
+    fs::remove_file(path).unwrap();
+}
+
+criterion_group!(benches, bench_convert, bench_read_buffer, bench_gzip_output);
+criterion_main!(benches);
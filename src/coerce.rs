@@ -0,0 +1,124 @@
+use serde_json::Value;
+use std::str::FromStr;
+
+/// Which strings in a record are turned into the JSON values they spell.
+/// Only exact spellings count: `42`, `-2.5` and `1e3` are numbers, but
+/// `007`, `1,000` and ` 42` aren't, and `true` is a boolean but `True`
+/// isn't.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Coerce {
+    pub numbers: bool,
+    pub booleans: bool,
+    pub null: bool,
+}
+
+impl Coerce {
+    /// Coerces every kind of string.
+    pub const ALL: Coerce = Coerce {
+        numbers: true,
+        booleans: true,
+        null: true,
+    };
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.numbers || self.booleans || self.null
+    }
+
+    /// The value `s` spells, if it's a kind being coerced.
+    pub(crate) fn parse(&self, s: &str) -> Option<Value> {
+        // JSON allows whitespace around a value; a coerced string mustn't
+        // have any.
+        if s.trim() != s {
+            return None;
+        }
+
+        match serde_json::from_str(s).ok()? {
+            value @ Value::Number(_) if self.numbers => Some(value),
+            value @ Value::Bool(_) if self.booleans => Some(value),
+            Value::Null if self.null => Some(Value::Null),
+            _ => None,
+        }
+    }
+
+    /// Replaces each string in `value`, at any depth, with what it spells.
+    /// Object keys are left as they are.
+    pub(crate) fn apply(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => {
+                if let Some(coerced) = self.parse(s) {
+                    *value = coerced;
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.apply(value)),
+            Value::Object(map) => map.values_mut().for_each(|value| self.apply(value)),
+            _ => {}
+        }
+    }
+}
+
+impl FromStr for Coerce {
+    type Err = String;
+
+    /// Parses a comma-separated list of `numbers`, `booleans` and `null`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut coerce = Coerce::default();
+
+        for kind in s.split(',') {
+            match kind.trim() {
+                "numbers" => coerce.numbers = true,
+                "booleans" => coerce.booleans = true,
+                "null" => coerce.null = true,
+                kind => {
+                    return Err(format!(
+                        "expected numbers, booleans or null, got {:?}",
+                        kind
+                    ))
+                }
+            }
+        }
+
+        Ok(coerce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_apply() {
+        let mut record = json!({
+            "n": "42", "f": "-2.5", "b": "true", "z": "null",
+            "padded": "007", "spaced": " 42", "grouped": "1,000", "word": "True",
+            "nested": {"list": ["1", "x", 2]}
+        });
+        Coerce::ALL.apply(&mut record);
+
+        assert_eq!(
+            record,
+            json!({
+                "n": 42, "f": -2.5, "b": true, "z": null,
+                "padded": "007", "spaced": " 42", "grouped": "1,000", "word": "True",
+                "nested": {"list": [1, "x", 2]}
+            })
+        );
+
+        let mut record = json!({"n": "42", "b": "false", "z": "null"});
+        "booleans".parse::<Coerce>().unwrap().apply(&mut record);
+        assert_eq!(record, json!({"n": "42", "b": false, "z": "null"}));
+    }
+
+    #[test]
+    fn test_parse_kinds() {
+        assert_eq!(
+            "numbers,null".parse(),
+            Ok(Coerce {
+                numbers: true,
+                booleans: false,
+                null: true
+            })
+        );
+        assert!("dates".parse::<Coerce>().is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::Cli;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches, Parser};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where defaults are read from without `--config`, if the file exists.
+const DEFAULT_PATH: &str = ".jl2js.toml";
+
+/// Parses the command line `argv`, taking defaults for the options it
+/// doesn't give from a config file. Its keys are long option names, like
+/// `indent = 4` or `add-field = ["a=1", "b=2"]`. An option from the file is
+/// ignored if the command line gives it, or gives one it conflicts with, so
+/// `--compact` wins over `pretty = true`.
+pub fn parse(argv: Vec<OsString>) -> Result<Cli, clap::Error> {
+    let mut command = Cli::command();
+    let matches = command.try_get_matches_from_mut(&argv)?;
+    let args = Cli::from_arg_matches(&matches)?;
+
+    let path = match args.config {
+        Some(path) => path,
+        None if Path::new(DEFAULT_PATH).is_file() => PathBuf::from(DEFAULT_PATH),
+        None => return Ok(args),
+    };
+
+    let defaults = read_defaults(&command, &matches, &path).map_err(|message| {
+        command.error(
+            ErrorKind::InvalidValue,
+            format!("{}: {}", path.display(), message),
+        )
+    })?;
+
+    let mut argv = argv.into_iter();
+    let program = argv.next();
+
+    Cli::try_parse_from(program.into_iter().chain(defaults).chain(argv))
+}
+
+/// The arguments the config file at `path` adds to the command line.
+fn read_defaults(
+    command: &Command,
+    matches: &ArgMatches,
+    path: &Path,
+) -> Result<Vec<OsString>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+
+    let mut defaults = Vec::new();
+
+    for (key, value) in &table {
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .filter(|arg| !["config", "help", "version"].contains(&arg.get_id().as_str()))
+            .ok_or_else(|| format!("unknown option {:?}", key))?;
+
+        if !given(command, matches, arg) {
+            push_values(&mut defaults, &long, arg.get_action(), value)
+                .map_err(|message| format!("{}: {}", key, message))?;
+        }
+    }
+
+    Ok(defaults)
+}
+
+/// Whether the command line gave `arg`, or an option it conflicts with.
+fn given(command: &Command, matches: &ArgMatches, arg: &Arg) -> bool {
+    let on_command_line =
+        |arg: &Arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine);
+
+    on_command_line(arg)
+        || command
+            .get_arg_conflicts_with(arg)
+            .into_iter()
+            .any(on_command_line)
+        || command
+            .get_arguments()
+            .filter(|other| on_command_line(other))
+            .any(|other| {
+                command
+                    .get_arg_conflicts_with(other)
+                    .iter()
+                    .any(|conflict| conflict.get_id() == arg.get_id())
+            })
+}
+
+/// Adds `value` for `--long` to `args` as it would be written on the
+/// command line.
+fn push_values(
+    args: &mut Vec<OsString>,
+    long: &str,
+    action: &ArgAction,
+    value: &toml::Value,
+) -> Result<(), String> {
+    match (action, value) {
+        (ArgAction::SetTrue, toml::Value::Boolean(set)) => {
+            if *set {
+                args.push(format!("--{}", long).into());
+            }
+        }
+        (ArgAction::SetTrue, _) => return Err("expected true or false".to_string()),
+        (ArgAction::Count, toml::Value::Integer(count)) => {
+            for _ in 0..*count {
+                args.push(format!("--{}", long).into());
+            }
+        }
+        (ArgAction::Count, _) => return Err("expected a number".to_string()),
+        (_, toml::Value::Array(values)) => {
+            for value in values {
+                push_values(args, long, action, value)?;
+            }
+        }
+        (_, value) => {
+            let value = match value {
+                toml::Value::String(s) => s.clone(),
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Float(f) => f.to_string(),
+                toml::Value::Boolean(b) => b.to_string(),
+                _ => return Err("expected a string, number or boolean".to_string()),
+            };
+
+            args.push(format!("--{}={}", long, value).into());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1137 @@
+use crate::duplicates::duplicate_keys;
+use crate::error::type_name;
+use crate::infer::SchemaWriter;
+use crate::lines::NewlineCounter;
+use crate::msgpack::MsgpackWriter;
+use crate::reader::{parse_timed, JsonlReader, DEFAULT_READ_BUFFER_SIZE};
+use crate::rows::CsvReader;
+use crate::table::TableWriter;
+use crate::writer::{RecordWriter, Style};
+use crate::yaml::YamlWriter;
+use crate::{
+    merge, transform, FloatFormat, Format, InputFormat, Jl2jsError, OnDuplicate, Options, Stats,
+};
+use jsonschema::Validator;
+use rand::distr::Bernoulli;
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use serde::de::IgnoredAny;
+use serde_json::{Map, Value};
+use std::cell::Cell;
+use std::collections::{HashSet, VecDeque};
+use std::io::{BufReader, Read, Write};
+use std::sync::atomic::Ordering;
+use std::time::{Instant, SystemTime};
+
+/// Lines handed to each worker per batch when parsing in parallel.
+const LINES_PER_JOB: usize = 256;
+
+/// Names the first option in `options` that needs each record parsed into a
+/// [`Value`], which `passthrough` skips.
+fn needs_values(options: &Options) -> Option<&'static str> {
+    #[cfg(feature = "relaxed")]
+    if options.relaxed {
+        return Some("relaxed");
+    }
+
+    [
+        (options.format == Format::Csv, "CSV output"),
+        (options.format == Format::Tsv, "TSV output"),
+        (options.format == Format::Yaml, "YAML output"),
+        (options.format == Format::Msgpack, "MessagePack output"),
+        (options.infer_schema, "infer_schema"),
+        (options.ascii, "ascii"),
+        (options.key_by.is_some(), "key_by"),
+        (options.group_by.is_some(), "group_by"),
+        (options.merge.is_some(), "merge"),
+        (options.pretty, "pretty"),
+        (options.compact, "compact"),
+        (
+            options.float_format != FloatFormat::Shortest,
+            "float_format",
+        ),
+        (options.sort_keys, "sort_keys"),
+        (options.canonical, "canonical"),
+        (options.ignore_trailing, "ignore_trailing"),
+        (options.whitespace_separated, "whitespace_separated"),
+        (options.lines_as_strings, "lines_as_strings"),
+        (options.schema.is_some(), "schema"),
+        (options.require_uniform_keys, "require_uniform_keys"),
+        (options.max_depth.is_some(), "max_depth"),
+        (options.ascii_keys_only, "ascii_keys_only"),
+        (options.unwrap_arrays, "unwrap_arrays"),
+        (options.filter.is_some(), "filter"),
+        (options.extract.is_some(), "extract"),
+        (options.jsonpath.is_some(), "jsonpath"),
+        (options.dedup.is_some(), "dedup"),
+        (!options.rename.is_empty(), "rename"),
+        (options.coerce.is_enabled(), "coerce"),
+        (options.select.is_some(), "select"),
+        (!options.drop.is_empty(), "drop"),
+        (options.flatten.is_some(), "flatten"),
+        (options.drop_nulls, "drop_nulls"),
+        (options.normalize_newlines, "normalize_newlines"),
+        (!options.add_fields.is_empty(), "add_fields"),
+        (options.add_timestamp.is_some(), "add_timestamp"),
+        (options.line_number_key.is_some(), "line_number_key"),
+        (options.ensure_object, "ensure_object"),
+        (options.wrap_scalars.is_some(), "wrap_scalars"),
+    ]
+    .into_iter()
+    .find_map(|(set, option)| set.then_some(option))
+}
+
+/// A record transform given by a library user, which returns whether to
+/// keep the record.
+pub(crate) type Transform<'a> =
+    Box<dyn FnMut(&mut Value) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> + 'a>;
+
+/// Drives a conversion: reads lines, parses them and writes the resulting
+/// records.
+pub(crate) struct Converter<'a, W: Write> {
+    options: &'a Options,
+    output: Output<W>,
+    /// Set when output is split into several chunks.
+    chunks: Option<Chunks<'a, W>>,
+    transform: Option<Transform<'a>>,
+    pool: Option<ThreadPool>,
+    schema: Option<Validator>,
+    /// Decides which records are kept when sampling.
+    sampler: Option<(Bernoulli, StdRng)>,
+    skipped: usize,
+    failed_inputs: usize,
+    /// Records that have made it past the filters, for `skip` and `limit`.
+    matched: usize,
+    /// Records read, for `max_records`.
+    seen: usize,
+    /// The line being converted, for `collect_errors`.
+    text: Option<String>,
+    /// Why the first skipped record was skipped.
+    first_error: Option<Jl2jsError>,
+    /// Keys already written when keying by a field.
+    seen_keys: HashSet<String>,
+    /// What records already written were compared by, for `dedup`.
+    seen_records: HashSet<String>,
+    /// The first record's keys, for `require_uniform_keys`.
+    uniform_keys: Option<Vec<String>>,
+    /// Records buffered until the end when duplicate keys keep the last, or
+    /// the object records are merged into.
+    keyed: Map<String, Value>,
+}
+
+impl<'a, W: Write> Converter<'a, W> {
+    pub(crate) fn new(writer: W, options: &'a Options) -> Result<Self, Jl2jsError> {
+        let pool = match options.jobs {
+            jobs if jobs > 1 => Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(jobs)
+                    .build()
+                    .map_err(std::io::Error::other)?,
+            ),
+            _ => None,
+        };
+
+        if options.input_format == InputFormat::Csv {
+            if let Some((_, option)) = [
+                (options.passthrough, "passthrough"),
+                (options.whitespace_separated, "whitespace_separated"),
+                (options.lines_as_strings, "lines_as_strings"),
+            ]
+            .into_iter()
+            .find(|(set, _)| *set)
+            {
+                let message = format!("CSV input can't be combined with {}", option);
+                return Err(Jl2jsError::Usage(message));
+            }
+        }
+
+        if options.lines_as_strings && options.whitespace_separated {
+            let message = "lines_as_strings can't be combined with whitespace_separated";
+            return Err(Jl2jsError::Usage(message.to_string()));
+        }
+
+        if options.passthrough {
+            if let Some(option) = needs_values(options) {
+                let message = format!("passthrough can't be combined with {}", option);
+                return Err(Jl2jsError::Usage(message));
+            }
+        }
+
+        let schema = options
+            .schema
+            .as_ref()
+            .map(jsonschema::validator_for)
+            .transpose()
+            .map_err(|e| Jl2jsError::InvalidSchema(e.to_string()))?;
+
+        let sampler = match options.sample {
+            Some(probability) => {
+                let bernoulli = Bernoulli::new(probability).map_err(|e| {
+                    Jl2jsError::Usage(format!("invalid sample probability {}: {}", probability, e))
+                })?;
+                let rng = match options.seed {
+                    Some(seed) => StdRng::seed_from_u64(seed),
+                    #[cfg(feature = "cli")]
+                    None => rand::make_rng(),
+                    #[cfg(not(feature = "cli"))]
+                    None => {
+                        let message = "sampling needs a seed without the cli feature";
+                        return Err(Jl2jsError::Usage(message.to_string()));
+                    }
+                };
+
+                Some((bernoulli, rng))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            options,
+            output: Output::new(writer, options)?,
+            chunks: None,
+            transform: None,
+            pool,
+            schema,
+            sampler,
+            skipped: 0,
+            failed_inputs: 0,
+            matched: 0,
+            seen: 0,
+            text: None,
+            first_error: None,
+            seen_keys: HashSet::new(),
+            seen_records: HashSet::new(),
+            uniform_keys: None,
+            keyed: Map::new(),
+        })
+    }
+
+    /// Writes at most `size` records to each writer, opening the writer for
+    /// the chunk at an index with `open` once the ones before it are full.
+    /// Chunk 0 is the writer passed to [`Converter::new`].
+    pub(crate) fn split<F>(mut self, size: usize, open: F) -> Self
+    where
+        F: FnMut(usize) -> std::io::Result<W> + 'a,
+    {
+        self.chunks = Some(Chunks {
+            size,
+            open: Box::new(open),
+            index: 0,
+            records: 0,
+            bytes: 0,
+        });
+
+        self
+    }
+
+    /// Hands each record to `transform` before `skip` and `limit` are
+    /// applied, as documented for [`convert_with`](crate::convert_with).
+    pub(crate) fn with_transform(mut self, transform: Transform<'a>) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    /// Converts an input that may have failed to open.
+    pub(crate) fn convert_input<R: Read>(
+        &mut self,
+        reader: std::io::Result<R>,
+    ) -> Result<(), Jl2jsError> {
+        match reader {
+            Ok(reader) => self.convert(reader),
+            Err(e) => self.input_failed(e),
+        }
+    }
+
+    /// Gives up on an input that couldn't be read, moving on to the next one
+    /// if `continue_on_io_error` is set.
+    fn input_failed(&mut self, e: std::io::Error) -> Result<(), Jl2jsError> {
+        if !self.options.continue_on_io_error {
+            return Err(e.into());
+        }
+
+        self.failed_inputs += 1;
+
+        Ok(())
+    }
+
+    pub(crate) fn convert<R: Read>(&mut self, reader: R) -> Result<(), Jl2jsError> {
+        if self.options.input_format == InputFormat::Csv {
+            return self.convert_csv(reader);
+        }
+
+        if self.options.whitespace_separated {
+            return self.convert_values(reader);
+        }
+
+        let started = Instant::now();
+        let mut reader = JsonlReader::with_options(reader, self.options);
+
+        // Without a pool every line is parsed and written before the next
+        // one is read, so output keeps up with slow input.
+        let batch_size = match self.pool {
+            Some(_) => self.options.jobs * LINES_PER_JOB,
+            None => 1,
+        };
+
+        let mut batch = Vec::with_capacity(batch_size);
+        // Lines that couldn't be read, after how many lines of the batch,
+        // so they're handled in order with the lines around them.
+        let mut failures = VecDeque::new();
+        let mut failed = None;
+
+        while !self.done() && failed.is_none() {
+            // Any line may be a record, so none past the limit are read.
+            let wanted = batch_size.min(self.remaining().unwrap_or(usize::MAX));
+
+            while batch.len() + failures.len() < wanted {
+                match reader.next_line() {
+                    Some(Ok(line)) => batch.push(line),
+                    Some(Err(Jl2jsError::Io(e))) => {
+                        failed = Some(e);
+                        break;
+                    }
+                    Some(Err(e)) => failures.push_back((batch.len(), e)),
+                    None => break,
+                }
+            }
+
+            if batch.is_empty() && failures.is_empty() {
+                break;
+            }
+
+            if self.options.warn_duplicate_keys {
+                for (number, line) in &batch {
+                    for pointer in duplicate_keys(line) {
+                        log::warn!("line {}: duplicate key {}", number, pointer);
+                    }
+                }
+            }
+
+            if self.options.lines_as_strings {
+                for (position, (number, line)) in batch.drain(..).enumerate() {
+                    if !self.catch_up(&mut failures, position)? {
+                        break;
+                    }
+
+                    self.text = None;
+                    self.write(number, Ok(Value::String(line)))?;
+                }
+            } else if self.options.passthrough {
+                let validated = self.map_batch(&batch, |_, line| {
+                    serde_json::from_str::<IgnoredAny>(line).map(|_| ())
+                });
+
+                for (position, ((number, line), result)) in
+                    batch.drain(..).zip(validated).enumerate()
+                {
+                    if !self.catch_up(&mut failures, position)? {
+                        break;
+                    }
+
+                    self.pass_through(number, line, result)?;
+                }
+            } else {
+                let options = self.options;
+                let parsed =
+                    self.map_batch(&batch, |number, line| parse_timed(number, line, options));
+
+                for (position, ((number, line), result)) in batch.drain(..).zip(parsed).enumerate()
+                {
+                    if !self.catch_up(&mut failures, position)? {
+                        break;
+                    }
+
+                    self.text = Some(line);
+                    self.write(number, result)?;
+                }
+            }
+
+            // Failures after the last line of the batch.
+            self.catch_up(&mut failures, usize::MAX)?;
+            failures.clear();
+        }
+
+        // As when reading a line at a time, the input only fails if the limit
+        // wasn't reached first.
+        if let Some(e) = failed.filter(|_| !self.reached_limit()) {
+            return self.input_failed(e);
+        }
+
+        log::debug!(
+            "read {} lines, {} bytes in {:?}",
+            reader.line(),
+            reader.bytes(),
+            started.elapsed()
+        );
+
+        Ok(())
+    }
+
+    /// Handles the failures to read lines that came before line `position`
+    /// of a batch. Returns whether to carry on with the batch, which isn't
+    /// the case once the limit is reached. Lines already read are still
+    /// written when stopped otherwise, as when interrupted.
+    fn catch_up(
+        &mut self,
+        failures: &mut VecDeque<(usize, Jl2jsError)>,
+        position: usize,
+    ) -> Result<bool, Jl2jsError> {
+        while failures
+            .front()
+            .is_some_and(|(before, _)| *before <= position)
+        {
+            if self.reached_limit() {
+                return Ok(false);
+            }
+
+            if let Some((_, e)) = failures.pop_front() {
+                self.text = None;
+                self.skip_or(e)?;
+            }
+        }
+
+        Ok(!self.reached_limit())
+    }
+
+    /// Like [`Converter::convert`], but for JSON values separated by any
+    /// whitespace rather than one per line.
+    fn convert_values<R: Read>(&mut self, reader: R) -> Result<(), Jl2jsError> {
+        let newlines = Cell::new(0);
+        let capacity = self
+            .options
+            .read_buffer_size
+            .unwrap_or(DEFAULT_READ_BUFFER_SIZE);
+        let reader = NewlineCounter::new(BufReader::with_capacity(capacity, reader), &newlines);
+
+        for value in serde_json::Deserializer::from_reader(reader).into_iter::<Value>() {
+            if self.done() {
+                break;
+            }
+
+            self.text = None;
+
+            match value {
+                // Records are numbered by the line they end on.
+                Ok(value) => self.write(newlines.get() + 1, Ok(value))?,
+                Err(e) if e.is_io() => return self.input_failed(e.into()),
+                // There's no telling where the next value starts, so the
+                // rest of the input is passed over.
+                Err(e) => {
+                    self.write(e.line(), Err(e))?;
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Like [`Converter::convert`], but for the rows of CSV with a header.
+    fn convert_csv<R: Read>(&mut self, reader: R) -> Result<(), Jl2jsError> {
+        let mut reader = CsvReader::new(reader, self.options);
+
+        while !self.done() {
+            self.text = None;
+
+            match reader.next_record() {
+                Some(Ok((number, record))) => self.write(number, Ok(record))?,
+                Some(Err(Jl2jsError::Io(e))) => return self.input_failed(e),
+                Some(Err(e)) => self.skip_or(e)?,
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn finish(&mut self) -> Result<(), Jl2jsError> {
+        for (key, value) in std::mem::take(&mut self.keyed) {
+            self.output.write_entry(&key, &value)?;
+        }
+
+        if let Some(log) = &self.options.collect_errors {
+            log.flush()?;
+        }
+
+        self.output.finish()
+    }
+
+    pub(crate) fn stats(&self) -> Stats {
+        let (mut records, mut bytes) = self.output.written();
+
+        if let Some(chunks) = &self.chunks {
+            records += chunks.records;
+            bytes += chunks.bytes;
+        }
+
+        Stats {
+            records,
+            skipped: self.skipped,
+            bytes,
+            failed_inputs: self.failed_inputs,
+        }
+    }
+
+    /// Moves on to the next chunk of output if the current one is full.
+    fn next_chunk(&mut self) -> Result<(), Jl2jsError> {
+        let Some(chunks) = &mut self.chunks else {
+            return Ok(());
+        };
+
+        let (records, bytes) = self.output.written();
+
+        if records < chunks.size {
+            return Ok(());
+        }
+
+        self.output.finish()?;
+        chunks.records += records;
+        chunks.bytes += bytes;
+        chunks.index += 1;
+
+        self.output = Output::new((chunks.open)(chunks.index)?, self.options)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn take_first_error(&mut self) -> Option<Jl2jsError> {
+        self.first_error.take()
+    }
+
+    /// Whether no more input is read, because of `limit` or `stop`.
+    fn done(&self) -> bool {
+        self.reached_limit()
+            || matches!(&self.output, Output::Json(writer) if writer.full())
+            || self
+                .options
+                .stop
+                .as_ref()
+                .is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+
+    fn reached_limit(&self) -> bool {
+        self.remaining() == Some(0)
+    }
+
+    /// How many more records can be matched before the limit is reached.
+    fn remaining(&self) -> Option<usize> {
+        self.options
+            .limit
+            .map(|limit| (self.options.skip + limit).saturating_sub(self.matched))
+    }
+
+    /// Whether any transform that only applies to objects is configured.
+    fn transforms_objects(&self) -> bool {
+        self.options.select.is_some()
+            || !self.options.rename.is_empty()
+            || self.options.coerce.is_enabled()
+            || !self.options.drop.is_empty()
+            || self.options.flatten.is_some()
+            || !self.options.add_fields.is_empty()
+            || self.options.add_timestamp.is_some()
+    }
+
+    /// Applies `f` to each line of a batch, in parallel when a pool is
+    /// configured. The results are in the same order as the lines.
+    fn map_batch<T, F>(&self, batch: &[(usize, String)], f: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(usize, &str) -> T + Sync,
+    {
+        match &self.pool {
+            Some(pool) => pool.install(|| {
+                batch
+                    .par_iter()
+                    .map(|(number, line)| f(*number, line))
+                    .collect()
+            }),
+            None => batch
+                .iter()
+                .map(|(number, line)| f(*number, line))
+                .collect(),
+        }
+    }
+
+    /// Copies a line that was only checked to be valid JSON to the output.
+    fn pass_through(
+        &mut self,
+        number: usize,
+        line: String,
+        validated: serde_json::Result<()>,
+    ) -> Result<(), Jl2jsError> {
+        if let Err(source) = validated {
+            self.text = Some(line);
+
+            return self.skip_or(Jl2jsError::Parse {
+                line: number,
+                source,
+            });
+        }
+
+        self.see(number)?;
+
+        if self.sampled_out() || !self.admit() {
+            return Ok(());
+        }
+
+        self.next_chunk()?;
+
+        if let Output::Json(writer) = &mut self.output {
+            writer.write_raw(line.trim())?;
+        }
+
+        if self.options.flush {
+            self.output.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Counts a record read, failing once there are more than
+    /// `max_records`.
+    fn see(&mut self, number: usize) -> Result<(), Jl2jsError> {
+        self.seen += 1;
+
+        match self.options.max_records {
+            Some(max) if self.seen > max => Err(Jl2jsError::TooManyRecords { line: number, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether sampling passes over the current record.
+    fn sampled_out(&mut self) -> bool {
+        self.sampler
+            .as_mut()
+            .is_some_and(|(bernoulli, rng)| !rng.sample(*bernoulli))
+    }
+
+    /// Counts a record that made it past the filters towards `skip` and
+    /// `limit`, returning whether it should be written. Every check that can
+    /// skip a record has to come first, so that records skipped for an
+    /// error, like a rename conflict or a missing `key_by` field, aren't
+    /// counted.
+    fn admit(&mut self) -> bool {
+        // Lines already parsed in the same batch can come after the limit.
+        if self.reached_limit() {
+            return false;
+        }
+
+        self.matched += 1;
+
+        self.matched > self.options.skip
+    }
+
+    fn write(
+        &mut self,
+        number: usize,
+        parsed: serde_json::Result<Value>,
+    ) -> Result<(), Jl2jsError> {
+        let value = match parsed {
+            Ok(value) => value,
+            Err(source) => {
+                return self.skip_or(Jl2jsError::Parse {
+                    line: number,
+                    source,
+                })
+            }
+        };
+
+        match value {
+            Value::Array(values) if self.options.unwrap_arrays => {
+                for value in values {
+                    self.process(number, value)?;
+                }
+            }
+            value => self.process(number, value)?,
+        }
+
+        if self.options.flush {
+            self.output.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Validates, filters and transforms a record from line `number`, then
+    /// writes it.
+    fn process(&mut self, number: usize, mut value: Value) -> Result<(), Jl2jsError> {
+        self.see(number)?;
+
+        if let Some(max) = self
+            .options
+            .max_depth
+            .filter(|max| transform::depth(&value) > *max)
+        {
+            return self.skip_or(Jl2jsError::TooDeep { line: number, max });
+        }
+
+        if self.options.ascii_keys_only {
+            if let Some(key) = transform::non_ascii_key(&value) {
+                let error = Jl2jsError::NonAsciiKey {
+                    line: number,
+                    key: key.to_string(),
+                };
+
+                return self.skip_or(error);
+            }
+        }
+
+        if self.options.ensure_object && !value.is_object() {
+            let error = Jl2jsError::NotAnObject {
+                line: number,
+                found: type_name(&value),
+            };
+
+            return self.skip_or(error);
+        }
+
+        if let Some(Err(e)) = self.schema.as_ref().map(|schema| schema.validate(&value)) {
+            let error = Jl2jsError::SchemaViolation {
+                line: number,
+                path: e.instance_path().to_string(),
+                message: e.to_string(),
+            };
+
+            return self.skip_or(error);
+        }
+
+        if self.options.require_uniform_keys {
+            if let Some(error) = self.keys_differ(number, &value) {
+                return self.skip_or(error);
+            }
+        }
+
+        if self
+            .options
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !filter.matches(&value))
+        {
+            return Ok(());
+        }
+
+        if self.sampled_out() {
+            return Ok(());
+        }
+
+        if let Some(path) = &self.options.extract {
+            match transform::extract(&mut value, path) {
+                Some(extracted) => value = extracted,
+                None => return Ok(()),
+            }
+        }
+
+        match &self.options.jsonpath {
+            Some(path) => {
+                for selected in path.select(&value) {
+                    self.process_selected(number, selected)?;
+                }
+
+                Ok(())
+            }
+            None => self.process_selected(number, value),
+        }
+    }
+
+    /// Carries on processing a record once it's been narrowed down to the
+    /// values selected from it.
+    fn process_selected(&mut self, number: usize, mut value: Value) -> Result<(), Jl2jsError> {
+        if let Some(key) = &self.options.wrap_scalars {
+            value = transform::wrap_scalar(value, key);
+        }
+
+        if self.transforms_objects() && !value.is_object() && self.options.skip_non_objects {
+            return Ok(());
+        }
+
+        if let Some(key) = self
+            .options
+            .dedup
+            .as_ref()
+            .and_then(|dedup| dedup.key_of(&value))
+        {
+            if !self.seen_records.insert(key) {
+                return Ok(());
+            }
+        }
+
+        let kept = match &mut self.transform {
+            Some(transform) => transform(&mut value),
+            None => Ok(true),
+        };
+
+        match kept {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(source) => {
+                let error = Jl2jsError::Transform {
+                    line: number,
+                    source,
+                };
+
+                return self.skip_or(error);
+            }
+        }
+
+        if let Value::Object(map) = &mut value {
+            for (from, to) in &self.options.rename {
+                if !transform::rename(map, from, to, self.options.rename_overwrite) {
+                    let error = Jl2jsError::RenameConflict {
+                        line: number,
+                        from: from.clone(),
+                        to: to.clone(),
+                    };
+
+                    return self.skip_or(error);
+                }
+            }
+        }
+
+        if value.is_object() {
+            self.options.coerce.apply(&mut value);
+        }
+
+        if let (Some(keys), Value::Object(map)) = (&self.options.select, &mut value) {
+            transform::select(map, keys);
+        }
+
+        if let Value::Object(map) = &mut value {
+            for path in &self.options.drop {
+                transform::remove_path(map, path);
+            }
+        }
+
+        if let (Some(separator), Value::Object(map)) = (&self.options.flatten, &mut value) {
+            *map = transform::flatten(std::mem::take(map), separator);
+        }
+
+        if self.options.drop_nulls {
+            transform::drop_nulls(&mut value);
+        }
+
+        if self.options.normalize_newlines {
+            transform::normalize_newlines(&mut value);
+        }
+
+        if let Value::Object(map) = &mut value {
+            transform::add_fields(
+                map,
+                &self.options.add_fields,
+                self.options.keep_existing_fields,
+            );
+
+            if let Some(key) = &self.options.add_timestamp {
+                let timestamp = vec![(key.clone(), transform::rfc3339(SystemTime::now()).into())];
+                transform::add_fields(map, &timestamp, self.options.keep_existing_fields);
+            }
+        }
+
+        if let Some(key) = &self.options.line_number_key {
+            value = transform::with_line_number(value, key, number);
+        }
+
+        if self.options.canonical {
+            transform::sort_keys_by_utf16(&mut value);
+        } else if self.options.sort_keys || (self.options.compact && !self.options.pretty) {
+            transform::sort_keys(&mut value);
+        }
+
+        if let Some(error) = self.unwritable(number, &value) {
+            return self.skip_or(error);
+        }
+
+        if !self.admit() {
+            return Ok(());
+        }
+
+        self.emit(number, value)
+    }
+
+    /// Checks that the output can take `value`, once it's been transformed.
+    /// Records it can't take are skipped before they're counted towards
+    /// `skip` and `limit`.
+    fn unwritable(&self, number: usize, value: &Value) -> Option<Jl2jsError> {
+        let not_an_object = || Jl2jsError::NotAnObject {
+            line: number,
+            found: type_name(value),
+        };
+
+        match &self.output {
+            Output::Json(_) | Output::Msgpack(_) => {}
+            Output::Schema(_) | Output::Yaml(_) => return None,
+            Output::Table(table) => {
+                let Value::Object(map) = value else {
+                    return Some(not_an_object());
+                };
+
+                return table
+                    .unexpected_key(map)
+                    .map(|key| Jl2jsError::UnexpectedKey {
+                        line: number,
+                        key: key.clone(),
+                    });
+            }
+        }
+
+        if self.options.merge.is_some() {
+            return (!value.is_object()).then(not_an_object);
+        }
+
+        if self.options.group_by.is_some() {
+            return None;
+        }
+
+        let key_by = self.options.key_by.as_ref()?;
+
+        key_by
+            .key_of(value)
+            .is_none()
+            .then(|| Jl2jsError::MissingKey {
+                line: number,
+                field: key_by.field.clone(),
+            })
+    }
+
+    /// Writes a finished record to the output, once [`Converter::unwritable`]
+    /// has found nothing wrong with it.
+    fn emit(&mut self, number: usize, value: Value) -> Result<(), Jl2jsError> {
+        self.next_chunk()?;
+
+        match &mut self.output {
+            Output::Json(_) | Output::Msgpack(_) => {}
+            Output::Schema(schema) => {
+                schema.add(&value);
+                return Ok(());
+            }
+            Output::Yaml(yaml) => return yaml.write_record(&value),
+            Output::Table(table) => {
+                if let Value::Object(map) = value {
+                    table.write_row(map)?;
+                }
+
+                return Ok(());
+            }
+        }
+
+        if let Some(arrays) = self.options.merge {
+            if let Value::Object(map) = value {
+                merge::deep_merge(&mut self.keyed, map, arrays);
+            }
+
+            return Ok(());
+        }
+
+        if let Some(group_by) = &self.options.group_by {
+            if let Some(group) = group_by.group_of(&value) {
+                let records = self
+                    .keyed
+                    .entry(group)
+                    .or_insert_with(|| Value::Array(Vec::new()));
+
+                if let Value::Array(records) = records {
+                    records.push(value);
+                }
+            }
+
+            return Ok(());
+        }
+
+        let Some(key_by) = &self.options.key_by else {
+            self.output.write_record(&value)?;
+            return Ok(());
+        };
+
+        let Some(key) = key_by.key_of(&value) else {
+            return Ok(());
+        };
+
+        match key_by.on_duplicate {
+            OnDuplicate::Error => {
+                if self.seen_keys.contains(&key) {
+                    return Err(Jl2jsError::DuplicateKey { line: number, key });
+                }
+
+                self.output.write_entry(&key, &value)?;
+                self.seen_keys.insert(key);
+            }
+            OnDuplicate::KeepLast => {
+                self.keyed.insert(key, value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `value` is an object with the same keys as the first
+    /// record, which sets the keys every other record must have.
+    fn keys_differ(&mut self, number: usize, value: &Value) -> Option<Jl2jsError> {
+        let Value::Object(map) = value else {
+            return Some(Jl2jsError::NotAnObject {
+                line: number,
+                found: type_name(value),
+            });
+        };
+
+        let Some(keys) = &self.uniform_keys else {
+            self.uniform_keys = Some(map.keys().cloned().collect());
+            return None;
+        };
+
+        let missing: Vec<String> = keys
+            .iter()
+            .filter(|key| !map.contains_key(*key))
+            .cloned()
+            .collect();
+        let extra: Vec<String> = map
+            .keys()
+            .filter(|key| !keys.contains(key))
+            .cloned()
+            .collect();
+
+        if missing.is_empty() && extra.is_empty() {
+            return None;
+        }
+
+        Some(Jl2jsError::KeysDiffer {
+            line: number,
+            missing,
+            extra,
+        })
+    }
+
+    /// Skips the current record when lenient or collecting errors, or fails
+    /// with `error`.
+    fn skip_or(&mut self, error: Jl2jsError) -> Result<(), Jl2jsError> {
+        match &self.options.collect_errors {
+            Some(log) => log.record(&error, self.text.as_deref())?,
+            None if !self.options.lenient => return Err(error),
+            None => {}
+        }
+
+        self.skipped += 1;
+        self.first_error.get_or_insert(error);
+
+        Ok(())
+    }
+}
+
+/// Where records are written to.
+enum Output<W: Write> {
+    Json(RecordWriter<W>),
+    Table(Box<TableWriter<W>>),
+    Schema(Box<SchemaWriter<W>>),
+    Yaml(Box<YamlWriter<W>>),
+    Msgpack(Box<MsgpackWriter<W>>),
+}
+
+impl<W: Write> Output<W> {
+    fn new(writer: W, options: &Options) -> Result<Self, Jl2jsError> {
+        if options.max_output_bytes.is_some()
+            && (options.infer_schema
+                || matches!(
+                    options.format,
+                    Format::Csv | Format::Tsv | Format::Yaml | Format::Msgpack
+                ))
+        {
+            let message = "max_output_bytes only applies to JSON output";
+            return Err(Jl2jsError::Usage(message.to_string()));
+        }
+
+        if options.infer_schema {
+            return Ok(Output::Schema(Box::new(SchemaWriter::new(writer, options))));
+        }
+
+        let keyed =
+            options.key_by.is_some() || options.group_by.is_some() || options.merge.is_some();
+
+        if matches!(options.format, Format::Csv | Format::Tsv) && !keyed {
+            return Ok(Output::Table(Box::new(TableWriter::new(
+                writer,
+                options.format == Format::Tsv,
+                options.extend_header,
+                Style::new(options),
+            ))));
+        }
+
+        if options.format == Format::Yaml {
+            if keyed {
+                let message = "YAML output can't be keyed, grouped or merged";
+                return Err(Jl2jsError::Usage(message.to_string()));
+            }
+
+            return Ok(Output::Yaml(Box::new(YamlWriter::new(writer))));
+        }
+
+        if options.format == Format::Msgpack {
+            return Ok(Output::Msgpack(Box::new(MsgpackWriter::new(writer, keyed))));
+        }
+
+        let mut writer = RecordWriter::new(writer, options);
+        writer.begin()?;
+
+        Ok(Output::Json(writer))
+    }
+
+    /// The records and bytes written so far.
+    fn written(&self) -> (usize, u64) {
+        match self {
+            Output::Json(writer) => (writer.records(), writer.bytes()),
+            Output::Table(table) => (table.records(), table.bytes()),
+            Output::Schema(schema) => (schema.records(), schema.bytes()),
+            Output::Yaml(yaml) => (yaml.records(), yaml.bytes()),
+            Output::Msgpack(msgpack) => (msgpack.records(), msgpack.bytes()),
+        }
+    }
+
+    /// Writes a record, to output that isn't keyed.
+    fn write_record(&mut self, value: &Value) -> Result<(), Jl2jsError> {
+        match self {
+            Output::Json(writer) => writer.write_record(value),
+            Output::Msgpack(msgpack) => msgpack.write_record(value),
+            _ => unreachable!("records are written to JSON and MessagePack output here"),
+        }
+    }
+
+    /// Writes a record under `key`, for output keyed by a field.
+    fn write_entry(&mut self, key: &str, value: &Value) -> Result<(), Jl2jsError> {
+        match self {
+            Output::Json(writer) => writer.write_entry(key, value),
+            Output::Msgpack(msgpack) => msgpack.write_entry(key, value),
+            _ => unreachable!("only JSON and MessagePack output are keyed"),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Json(writer) => writer.flush(),
+            Output::Table(table) => table.flush(),
+            Output::Schema(schema) => schema.flush(),
+            Output::Yaml(yaml) => yaml.flush(),
+            Output::Msgpack(msgpack) => msgpack.flush(),
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), Jl2jsError> {
+        match self {
+            Output::Json(writer) => Ok(writer.finish()?),
+            Output::Table(table) => Ok(table.finish()?),
+            Output::Schema(schema) => schema.finish(),
+            Output::Yaml(yaml) => Ok(yaml.finish()?),
+            Output::Msgpack(msgpack) => Ok(msgpack.finish()?),
+        }
+    }
+}
+
+/// Output split across several writers.
+struct Chunks<'a, W> {
+    /// Records per chunk.
+    size: usize,
+    open: Box<dyn FnMut(usize) -> std::io::Result<W> + 'a>,
+    /// Index of the current chunk.
+    index: usize,
+    /// Records and bytes written to the chunks before the current one.
+    records: usize,
+    bytes: u64,
+}
@@ -0,0 +1,47 @@
+use crate::transform;
+use serde_json::Value;
+
+/// Which records count as duplicates of each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dedup {
+    /// Records equal as a whole, regardless of key order.
+    Record,
+    /// Records with equal values for this top-level field. Records without
+    /// the field are never duplicates.
+    Field(String),
+}
+
+impl Dedup {
+    /// Returns what `record` is compared by: the canonical text, with keys
+    /// sorted, of the record or its field.
+    pub(crate) fn key_of(&self, record: &Value) -> Option<String> {
+        let mut value = match self {
+            Dedup::Record => record.clone(),
+            Dedup::Field(field) => record.get(field)?.clone(),
+        };
+        transform::sort_keys(&mut value);
+
+        Some(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_key_of() {
+        let record = json!({"id": 1, "tags": {"b": 2, "a": 1}});
+
+        assert_eq!(
+            Dedup::Record.key_of(&record),
+            Dedup::Record.key_of(&json!({"tags": {"a": 1, "b": 2}, "id": 1}))
+        );
+        assert_eq!(
+            Dedup::Field("tags".to_string()).key_of(&record).as_deref(),
+            Some(r#"{"a":1,"b":2}"#)
+        );
+        assert_eq!(Dedup::Field("name".to_string()).key_of(&record), None);
+    }
+}
@@ -0,0 +1,122 @@
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Returns a JSON Pointer to each key in `json` that already appeared
+/// earlier in the same object, in the order they appear. `json` is scanned
+/// as text, since parsing it into a [`serde_json::Value`] keeps only the
+/// last of the duplicates. Anything up to where `json` stops being valid is
+/// scanned.
+pub(crate) fn duplicate_keys(json: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let scan = Scan {
+        path: String::new(),
+        found: &mut found,
+    };
+    let _ = scan.deserialize(&mut serde_json::Deserializer::from_str(json));
+
+    found
+}
+
+/// Scans the value at `path` for duplicate keys.
+struct Scan<'a> {
+    path: String,
+    found: &'a mut Vec<String>,
+}
+
+impl Scan<'_> {
+    fn child(&mut self, token: &str) -> Scan<'_> {
+        Scan {
+            path: format!(
+                "{}/{}",
+                self.path,
+                token.replace('~', "~0").replace('/', "~1")
+            ),
+            found: self.found,
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Scan<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Scan<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        let mut index = 0;
+
+        while seq
+            .next_element_seed(self.child(&index.to_string()))?
+            .is_some()
+        {
+            index += 1;
+        }
+
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        let mut keys = HashSet::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            let child = self.child(&key);
+
+            if keys.contains(&key) {
+                child.found.push(child.path.clone());
+            }
+
+            map.next_value_seed(child)?;
+            keys.insert(key);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_keys() {
+        assert_eq!(
+            duplicate_keys(r#"{"a":1,"b":{"c":1,"c":[{"d/e":1,"d/e":2}]},"a":2,"a":3}"#),
+            ["/b/c", "/b/c/0/d~1e", "/a", "/a"]
+        );
+        assert!(duplicate_keys(r#"[{"a":1},{"a":2}]"#).is_empty());
+    }
+}
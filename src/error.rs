@@ -0,0 +1,338 @@
+use serde_json::{json, Value};
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+/// Errors produced while converting between JSONL and JSON.
+#[derive(Debug)]
+pub enum Jl2jsError {
+    Io(std::io::Error),
+    /// A line of input could not be parsed as JSON. `line` is 1-based.
+    Parse {
+        line: usize,
+        source: serde_json::Error,
+    },
+    /// A line of input was longer than the configured maximum.
+    LineTooLong {
+        line: usize,
+        max: usize,
+    },
+    /// A line of input wasn't valid UTF-8. `column` is the 1-based byte
+    /// offset of the first invalid byte.
+    InvalidUtf8 {
+        line: usize,
+        column: usize,
+    },
+    /// A row of CSV input couldn't be read, such as one with a different
+    /// number of fields than the header.
+    InvalidCsv {
+        line: usize,
+        message: String,
+    },
+    /// There were more records than allowed.
+    TooManyRecords {
+        line: usize,
+        max: usize,
+    },
+    /// A record was nested more deeply than allowed.
+    TooDeep {
+        line: usize,
+        max: usize,
+    },
+    /// A record had an object key with non-ASCII characters, though keys
+    /// had to be ASCII.
+    NonAsciiKey {
+        line: usize,
+        key: String,
+    },
+    /// A record had no usable value for the field output is keyed by.
+    MissingKey {
+        line: usize,
+        field: String,
+    },
+    /// Two records had the same value for the field output is keyed by.
+    DuplicateKey {
+        line: usize,
+        key: String,
+    },
+    /// A record that had to be an object wasn't. `found` names its type.
+    NotAnObject {
+        line: usize,
+        found: &'static str,
+    },
+    /// A record had a key missing from the header of tabular output.
+    UnexpectedKey {
+        line: usize,
+        key: String,
+    },
+    /// A key couldn't be renamed because the record already had one by the
+    /// new name.
+    RenameConflict {
+        line: usize,
+        from: String,
+        to: String,
+    },
+    /// The transform given to [`convert_with`](crate::convert_with) failed
+    /// on a record.
+    Transform {
+        line: usize,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A record's keys weren't the same as the first record's, though they
+    /// had to be. `missing` are the first record's keys it lacked and
+    /// `extra` the keys it had besides.
+    KeysDiffer {
+        line: usize,
+        missing: Vec<String>,
+        extra: Vec<String>,
+    },
+    /// A record could not be serialized for the output.
+    Serialize(serde_json::Error),
+    /// Input converted back into JSONL wasn't an array. `found` names its
+    /// type.
+    NotAnArray {
+        found: &'static str,
+    },
+    /// The JSON Schema records are validated against could not be compiled.
+    InvalidSchema(String),
+    /// The options given were invalid, or can't be combined.
+    Usage(String),
+    /// A record did not conform to the JSON Schema. `path` is a JSON
+    /// Pointer to the offending value within the record.
+    SchemaViolation {
+        line: usize,
+        path: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for Jl2jsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Jl2jsError::Io(e) => write!(f, "{}", e),
+            // Errors without a position, such as those from the relaxed parser.
+            Jl2jsError::Parse { line, source } if source.line() == 0 => {
+                write!(f, "line {}: {}", line, source)
+            }
+            Jl2jsError::Parse { line, source } => {
+                // Each line is parsed on its own, so serde's position is always
+                // "line 1"; report the input line and column instead.
+                let message = source.to_string();
+                let position = format!(" at line {} column {}", source.line(), source.column());
+                let message = message.strip_suffix(&position).unwrap_or(&message);
+
+                write!(
+                    f,
+                    "line {}: {} at column {}",
+                    line,
+                    message,
+                    source.column()
+                )
+            }
+            Jl2jsError::LineTooLong { line, max } => {
+                write!(f, "line {}: longer than the maximum of {} bytes", line, max)
+            }
+            Jl2jsError::InvalidUtf8 { line, column } => {
+                write!(f, "line {}: invalid UTF-8 at column {}", line, column)
+            }
+            Jl2jsError::MissingKey { line, field } => {
+                write!(
+                    f,
+                    "line {}: no string, number or boolean {:?} field",
+                    line, field
+                )
+            }
+            Jl2jsError::DuplicateKey { line, key } => {
+                write!(f, "line {}: duplicate key {:?}", line, key)
+            }
+            Jl2jsError::NotAnObject { line, found } => {
+                write!(f, "line {}: expected an object, found {}", line, found)
+            }
+            Jl2jsError::UnexpectedKey { line, key } => {
+                write!(f, "line {}: key {:?} is not in the header", line, key)
+            }
+            Jl2jsError::InvalidCsv { line, message } => write!(f, "line {}: {}", line, message),
+            Jl2jsError::NonAsciiKey { line, key } => {
+                write!(f, "line {}: key {:?} is not ASCII", line, key)
+            }
+            Jl2jsError::TooManyRecords { line, max } => {
+                write!(f, "line {}: more than {} records", line, max)
+            }
+            Jl2jsError::TooDeep { line, max } => {
+                write!(f, "line {}: nested more than {} levels deep", line, max)
+            }
+            Jl2jsError::RenameConflict { line, from, to } => {
+                write!(
+                    f,
+                    "line {}: can't rename {:?} to {:?}, which already exists",
+                    line, from, to
+                )
+            }
+            Jl2jsError::Transform { line, source } => write!(f, "line {}: {}", line, source),
+            Jl2jsError::KeysDiffer {
+                line,
+                missing,
+                extra,
+            } => {
+                write!(f, "line {}: keys differ from the first record's", line)?;
+
+                let mut separator = ": ";
+
+                for (keys, name) in [(missing, "missing"), (extra, "extra")] {
+                    if !keys.is_empty() {
+                        write!(f, "{}{} {}", separator, name, keys.join(", "))?;
+                        separator = "; ";
+                    }
+                }
+
+                Ok(())
+            }
+            Jl2jsError::Serialize(e) => write!(f, "could not serialize record: {}", e),
+            Jl2jsError::NotAnArray { found } => {
+                write!(f, "expected a top-level JSON array, found {}", found)
+            }
+            Jl2jsError::InvalidSchema(message) => write!(f, "invalid schema: {}", message),
+            Jl2jsError::Usage(message) => write!(f, "{}", message),
+            Jl2jsError::SchemaViolation {
+                line,
+                path,
+                message,
+            } if path.is_empty() => write!(f, "line {}: {}", line, message),
+            Jl2jsError::SchemaViolation {
+                line,
+                path,
+                message,
+            } => write!(f, "line {}: {} at {}", line, message, path),
+        }
+    }
+}
+
+impl Jl2jsError {
+    /// The 1-based input line the error is about, if it's about one.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Jl2jsError::Parse { line, .. }
+            | Jl2jsError::LineTooLong { line, .. }
+            | Jl2jsError::InvalidUtf8 { line, .. }
+            | Jl2jsError::MissingKey { line, .. }
+            | Jl2jsError::DuplicateKey { line, .. }
+            | Jl2jsError::NotAnObject { line, .. }
+            | Jl2jsError::UnexpectedKey { line, .. }
+            | Jl2jsError::InvalidCsv { line, .. }
+            | Jl2jsError::TooManyRecords { line, .. }
+            | Jl2jsError::TooDeep { line, .. }
+            | Jl2jsError::NonAsciiKey { line, .. }
+            | Jl2jsError::RenameConflict { line, .. }
+            | Jl2jsError::Transform { line, .. }
+            | Jl2jsError::KeysDiffer { line, .. }
+            | Jl2jsError::SchemaViolation { line, .. } => Some(*line),
+            Jl2jsError::Io(_)
+            | Jl2jsError::Serialize(_)
+            | Jl2jsError::NotAnArray { .. }
+            | Jl2jsError::InvalidSchema(_)
+            | Jl2jsError::Usage(_) => None,
+        }
+    }
+}
+
+impl std::error::Error for Jl2jsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Jl2jsError::Io(e) => Some(e),
+            Jl2jsError::Parse { source, .. } | Jl2jsError::Serialize(source) => Some(source),
+            Jl2jsError::Transform { source, .. } => Some(&**source),
+            Jl2jsError::LineTooLong { .. }
+            | Jl2jsError::InvalidUtf8 { .. }
+            | Jl2jsError::MissingKey { .. }
+            | Jl2jsError::DuplicateKey { .. }
+            | Jl2jsError::NotAnObject { .. }
+            | Jl2jsError::UnexpectedKey { .. }
+            | Jl2jsError::InvalidCsv { .. }
+            | Jl2jsError::TooManyRecords { .. }
+            | Jl2jsError::TooDeep { .. }
+            | Jl2jsError::NonAsciiKey { .. }
+            | Jl2jsError::RenameConflict { .. }
+            | Jl2jsError::KeysDiffer { .. }
+            | Jl2jsError::NotAnArray { .. }
+            | Jl2jsError::InvalidSchema(_)
+            | Jl2jsError::Usage(_)
+            | Jl2jsError::SchemaViolation { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Jl2jsError {
+    fn from(e: std::io::Error) -> Self {
+        Jl2jsError::Io(e)
+    }
+}
+
+impl From<Jl2jsError> for std::io::Error {
+    fn from(e: Jl2jsError) -> Self {
+        match e {
+            Jl2jsError::Io(e) => e,
+            e @ Jl2jsError::Usage(_) => std::io::Error::new(std::io::ErrorKind::InvalidInput, e),
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Where lines skipped because of an error are written, as JSONL records
+/// like `{"line":2,"error":"expected value at column 1","text":"oops"}`.
+/// `text` is null when the line isn't at hand, as for lines that are too
+/// long. Clones write to the same place.
+#[derive(Clone)]
+pub struct ErrorLog(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl ErrorLog {
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        Self(Arc::new(Mutex::new(Box::new(BufWriter::new(writer)))))
+    }
+
+    pub(crate) fn record(&self, error: &Jl2jsError, text: Option<&str>) -> std::io::Result<()> {
+        let message = error.to_string();
+        let message = match error.line() {
+            Some(line) => message
+                .strip_prefix(&format!("line {}: ", line))
+                .unwrap_or(&message),
+            None => &message,
+        };
+        let record = json!({"line": error.line(), "error": message, "text": text});
+
+        let mut writer = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        serde_json::to_writer(&mut *writer, &record)?;
+        writer.write_all(b"\n")
+    }
+
+    pub(crate) fn flush(&self) -> std::io::Result<()> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+}
+
+impl fmt::Debug for ErrorLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ErrorLog")
+    }
+}
+
+/// Sorts an error from serializing a record into failures to write it and
+/// problems with the record itself.
+pub(crate) fn serialize_error(e: serde_json::Error) -> Jl2jsError {
+    if e.is_io() {
+        Jl2jsError::Io(e.into())
+    } else {
+        Jl2jsError::Serialize(e)
+    }
+}
+
+/// Names the JSON type of `value` for error messages.
+pub(crate) fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
@@ -0,0 +1,54 @@
+use serde_json::Value;
+use std::str::FromStr;
+
+/// Keeps only object records whose top-level `key` is the string `value`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub key: String,
+    pub value: String,
+}
+
+impl Filter {
+    pub fn matches(&self, record: &Value) -> bool {
+        match record.get(&self.key) {
+            Some(Value::String(value)) => *value == self.value,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    /// Parses a filter written as `key=value`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, value)) => Ok(Self {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            None => Err(format!("expected <key>=<value>, got {:?}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_filter_matches() {
+        let filter: Filter = "status=active".parse().unwrap();
+
+        assert!(filter.matches(&json!({"status": "active", "id": 1})));
+        assert!(!filter.matches(&json!({"status": "inactive"})));
+        assert!(!filter.matches(&json!({"id": 1})));
+        assert!(!filter.matches(&json!("status=active")));
+    }
+
+    #[test]
+    fn test_filter_parse_error() {
+        assert!("status".parse::<Filter>().is_err());
+    }
+}
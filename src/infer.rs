@@ -0,0 +1,223 @@
+use crate::writer::RecordWriter;
+use crate::{Format, Jl2jsError, Options, Wrap};
+use serde_json::{json, Map, Value};
+use std::io::Write;
+
+/// The types seen at one place in the records, and the shapes of the
+/// arrays and objects among them.
+#[derive(Debug, Default)]
+struct Shape {
+    null: bool,
+    boolean: bool,
+    integer: bool,
+    number: bool,
+    string: bool,
+    /// The union of every array's elements.
+    items: Option<Box<Shape>>,
+    object: Option<ObjectShape>,
+}
+
+#[derive(Debug, Default)]
+struct ObjectShape {
+    /// Objects seen.
+    count: usize,
+    /// Each key seen, with the union of its values and the number of
+    /// objects it was in.
+    properties: Vec<(String, Shape, usize)>,
+}
+
+impl Shape {
+    fn add(&mut self, value: &Value) {
+        match value {
+            Value::Null => self.null = true,
+            Value::Bool(_) => self.boolean = true,
+            Value::Number(n) if n.is_f64() => self.number = true,
+            Value::Number(_) => self.integer = true,
+            Value::String(_) => self.string = true,
+            Value::Array(values) => {
+                let items = self.items.get_or_insert_with(Default::default);
+
+                for value in values {
+                    items.add(value);
+                }
+            }
+            Value::Object(map) => self.object.get_or_insert_with(Default::default).add(map),
+        }
+    }
+
+    /// A schema every value added conforms to. Integers are left out of
+    /// the types when floats were also seen, since `number` covers both.
+    fn to_schema(&self) -> Map<String, Value> {
+        let types: Vec<&str> = [
+            (self.null, "null"),
+            (self.boolean, "boolean"),
+            (self.integer && !self.number, "integer"),
+            (self.number, "number"),
+            (self.string, "string"),
+            (self.items.is_some(), "array"),
+            (self.object.is_some(), "object"),
+        ]
+        .into_iter()
+        .filter_map(|(seen, name)| seen.then_some(name))
+        .collect();
+
+        let mut schema = Map::new();
+
+        match types.as_slice() {
+            [] => {}
+            [name] => {
+                schema.insert("type".into(), json!(name));
+            }
+            names => {
+                schema.insert("type".into(), json!(names));
+            }
+        }
+
+        // Only empty arrays having been seen says nothing about the items.
+        if let Some(items) = self.items.as_deref().map(Shape::to_schema) {
+            if !items.is_empty() {
+                schema.insert("items".into(), Value::Object(items));
+            }
+        }
+
+        if let Some(object) = &self.object {
+            let properties = object
+                .properties
+                .iter()
+                .map(|(key, shape, _)| (key.clone(), Value::Object(shape.to_schema())))
+                .collect();
+            schema.insert("properties".into(), Value::Object(properties));
+
+            let required: Vec<&String> = object
+                .properties
+                .iter()
+                .filter(|(_, _, count)| *count == object.count)
+                .map(|(key, _, _)| key)
+                .collect();
+
+            if !required.is_empty() {
+                schema.insert("required".into(), json!(required));
+            }
+        }
+
+        schema
+    }
+}
+
+impl ObjectShape {
+    fn add(&mut self, map: &Map<String, Value>) {
+        self.count += 1;
+
+        for (key, value) in map {
+            let index = match self.properties.iter().position(|(k, _, _)| k == key) {
+                Some(index) => index,
+                None => {
+                    self.properties.push((key.clone(), Shape::default(), 0));
+                    self.properties.len() - 1
+                }
+            };
+
+            let (_, shape, count) = &mut self.properties[index];
+            shape.add(value);
+            *count += 1;
+        }
+    }
+}
+
+/// Instead of writing records, infers a JSON Schema that all of them
+/// conform to and writes that once finished. A field is required if every
+/// object at its place had it.
+pub(crate) struct SchemaWriter<W: Write> {
+    writer: RecordWriter<W>,
+    shape: Shape,
+    records: usize,
+}
+
+impl<W: Write> SchemaWriter<W> {
+    pub(crate) fn new(writer: W, options: &Options) -> Self {
+        // The schema is written as a single document, formatted like a
+        // record would be.
+        let options = Options {
+            format: Format::Ndjson,
+            wrap: Wrap::None,
+            append: None,
+            key_by: None,
+            merge: None,
+            ..options.clone()
+        };
+
+        Self {
+            writer: RecordWriter::new(writer, &options),
+            shape: Shape::default(),
+            records: 0,
+        }
+    }
+
+    pub(crate) fn add(&mut self, record: &Value) {
+        self.shape.add(record);
+        self.records += 1;
+    }
+
+    pub(crate) fn records(&self) -> usize {
+        self.records
+    }
+
+    pub(crate) fn bytes(&self) -> u64 {
+        self.writer.bytes()
+    }
+
+    pub(crate) fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub(crate) fn finish(&mut self) -> Result<(), Jl2jsError> {
+        let mut schema = Map::new();
+        schema.insert(
+            "$schema".into(),
+            json!("https://json-schema.org/draft/2020-12/schema"),
+        );
+        schema.extend(self.shape.to_schema());
+
+        self.writer.write_record(&Value::Object(schema))?;
+        Ok(self.writer.finish()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_schema() {
+        let mut shape = Shape::default();
+
+        for record in [
+            json!({"id": 1, "name": "a", "tags": [], "size": 1.5}),
+            json!({"id": 2, "tags": ["x", null], "size": 2}),
+            json!({"id": 3, "name": null, "tags": [{"k": 1}, {"k": 2, "v": true}]}),
+        ] {
+            shape.add(&record);
+        }
+
+        assert_eq!(
+            Value::Object(shape.to_schema()),
+            json!({
+                "type": "object",
+                "properties": {
+                    "id": {"type": "integer"},
+                    "name": {"type": ["null", "string"]},
+                    "tags": {
+                        "type": "array",
+                        "items": {
+                            "type": ["null", "string", "object"],
+                            "properties": {"k": {"type": "integer"}, "v": {"type": "boolean"}},
+                            "required": ["k"]
+                        }
+                    },
+                    "size": {"type": "number"}
+                },
+                "required": ["id", "tags"]
+            })
+        );
+    }
+}
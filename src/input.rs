@@ -0,0 +1,317 @@
+use flate2::read::MultiGzDecoder;
+use std::fs::{self, File};
+#[cfg(feature = "compression")]
+use std::io::BufReader;
+use std::io::{stdin, Read, Stdin};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How long a followed file waits before checking for more input.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How input is compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    /// Needs the `compression` feature.
+    Zstd,
+    /// Needs the `compression` feature.
+    Bzip2,
+}
+
+impl Compression {
+    /// The compression implied by the extension of `path`: `.gz`, `.zst` or
+    /// `.bz2`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            "bz2" => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+}
+
+/// Where JSONL input is read from.
+pub enum InputSource {
+    File(File),
+    Stdin(Stdin),
+    Gzip(Box<MultiGzDecoder<InputSource>>),
+    #[cfg(feature = "compression")]
+    Zstd(Box<zstd::Decoder<'static, BufReader<InputSource>>>),
+    #[cfg(feature = "compression")]
+    Bzip2(Box<bzip2::read::MultiBzDecoder<InputSource>>),
+    Follow(Follower),
+    #[cfg(feature = "http")]
+    Http(Box<ureq::BodyReader<'static>>),
+}
+
+impl Read for InputSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            InputSource::File(file) => file.read(buf),
+            InputSource::Stdin(stdin) => stdin.read(buf),
+            InputSource::Gzip(decoder) => decoder.read(buf),
+            #[cfg(feature = "compression")]
+            InputSource::Zstd(decoder) => decoder.read(buf),
+            #[cfg(feature = "compression")]
+            InputSource::Bzip2(decoder) => decoder.read(buf),
+            InputSource::Follow(follower) => follower.read(buf),
+            #[cfg(feature = "http")]
+            InputSource::Http(body) => body.read(buf),
+        }
+    }
+}
+
+impl InputSource {
+    pub fn from_file(path: PathBuf) -> std::io::Result<Self> {
+        Ok(Self::File(File::open(path)?))
+    }
+
+    pub fn from_stdin() -> Self {
+        Self::Stdin(stdin())
+    }
+
+    /// Whether the input at `path` is a regular file, whose size is known up
+    /// front and which can be read more than once. Pipes aren't, including
+    /// the `/dev/fd` paths that process substitution like `<(zcat a.gz)`
+    /// passes. `path` isn't opened, since opening a named pipe waits for a
+    /// writer and closing it unread can kill the writer with SIGPIPE.
+    pub fn is_seekable(path: &Path) -> bool {
+        fs::metadata(path).is_ok_and(|metadata| metadata.is_file())
+    }
+
+    /// Reads the file at `path` like `tail -f`, waiting for more to be
+    /// appended at the end instead of stopping. Reading never ends.
+    pub fn follow(path: PathBuf) -> std::io::Result<Self> {
+        Ok(Self::Follow(Follower {
+            file: File::open(&path)?,
+            path,
+            position: 0,
+        }))
+    }
+
+    /// Streams the body of a GET request to `url`. Responses with a status
+    /// other than 2xx are an error.
+    #[cfg(feature = "http")]
+    pub fn from_url(url: &str) -> std::io::Result<Self> {
+        let response = ureq::get(url).call().map_err(|e| {
+            let message = match e {
+                ureq::Error::StatusCode(status) => format!("{}: HTTP status {}", url, status),
+                e => format!("{}: {}", url, e),
+            };
+
+            std::io::Error::other(message)
+        })?;
+
+        Ok(Self::Http(Box::new(response.into_body().into_reader())))
+    }
+
+    /// Decompresses this source as gzip while reading.
+    pub fn gzip(self) -> Self {
+        Self::Gzip(Box::new(MultiGzDecoder::new(self)))
+    }
+
+    /// Decompresses this source while reading. zstd and bzip2 are
+    /// unsupported without the `compression` feature.
+    pub fn decompress(self, compression: Compression) -> std::io::Result<Self> {
+        match compression {
+            Compression::Gzip => Ok(self.gzip()),
+            #[cfg(feature = "compression")]
+            Compression::Zstd => Ok(Self::Zstd(Box::new(zstd::Decoder::new(self)?))),
+            #[cfg(feature = "compression")]
+            Compression::Bzip2 => Ok(Self::Bzip2(Box::new(bzip2::read::MultiBzDecoder::new(
+                self,
+            )))),
+            #[cfg(not(feature = "compression"))]
+            Compression::Zstd | Compression::Bzip2 => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "reading zstd and bzip2 needs jl2js built with --features compression",
+            )),
+        }
+    }
+}
+
+/// A file being followed. If it's truncated, or replaced by another file at
+/// the same path as when logs are rotated, reading starts over from the
+/// beginning of whatever file is at the path.
+pub struct Follower {
+    file: File,
+    path: PathBuf,
+    /// Bytes read from `file` so far.
+    position: u64,
+}
+
+impl Follower {
+    fn replaced(&self) -> std::io::Result<bool> {
+        // The file may be missing for a moment while it's being rotated.
+        let Ok(current) = fs::metadata(&self.path) else {
+            return Ok(false);
+        };
+
+        if current.len() < self.position {
+            return Ok(true);
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let open = self.file.metadata()?;
+
+            if (current.dev(), current.ino()) != (open.dev(), open.ino()) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+impl Read for Follower {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let read = self.file.read(buf)?;
+
+            if read > 0 || buf.is_empty() {
+                self.position += read as u64;
+                return Ok(read);
+            }
+
+            if self.replaced()? {
+                self.file = File::open(&self.path)?;
+                self.position = 0;
+            } else {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(source: &mut InputSource) -> String {
+        let mut buf = [0; 16];
+        let read = source.read(&mut buf).unwrap();
+
+        String::from_utf8(buf[..read].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_follow() {
+        let path = std::env::temp_dir().join(format!("jl2js-{}-follow.jsonl", std::process::id()));
+
+        fs::write(&path, "first\n").unwrap();
+        let mut source = InputSource::follow(path.clone()).unwrap();
+        assert_eq!(read(&mut source), "first\n");
+
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        std::io::Write::write_all(&mut file, b"second\n").unwrap();
+        assert_eq!(read(&mut source), "second\n");
+
+        // Truncated and rewritten, as when a log is rotated in place.
+        fs::write(&path, "new\n").unwrap();
+        assert_eq!(read(&mut source), "new\n");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Serves a single HTTP response on a local port, returning its URL.
+    #[cfg(feature = "http")]
+    fn serve(response: &'static str) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/data.jsonl", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            std::io::Write::write_all(&mut stream, response.as_bytes()).unwrap();
+        });
+
+        url
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_from_url() {
+        let url =
+            serve("HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: close\r\n\r\n{}\n[]\n");
+        let mut body = String::new();
+        InputSource::from_url(&url)
+            .unwrap()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "{}\n[]\n");
+
+        let url = serve("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        let Err(e) = InputSource::from_url(&url) else {
+            panic!("expected an error");
+        };
+        assert_eq!(e.to_string(), format!("{}: HTTP status 404", url));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_decompress() {
+        use std::io::Write;
+
+        let records = "{\"a\":1}\n{\"a\":2}\n";
+
+        let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bzip2.write_all(records.as_bytes()).unwrap();
+
+        for (name, compressed) in [
+            ("zst", zstd::encode_all(records.as_bytes(), 0).unwrap()),
+            ("bz2", bzip2.finish().unwrap()),
+        ] {
+            let path = std::env::temp_dir().join(format!(
+                "jl2js-{}-decompress.jsonl.{}",
+                std::process::id(),
+                name
+            ));
+            fs::write(&path, compressed).unwrap();
+
+            let compression = Compression::from_path(&path).unwrap();
+            let mut contents = String::new();
+            InputSource::from_file(path.clone())
+                .unwrap()
+                .decompress(compression)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            fs::remove_file(path).unwrap();
+
+            assert_eq!(contents, records, "{}", name);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_seekable() {
+        use std::os::fd::{AsRawFd, OwnedFd};
+
+        let path =
+            std::env::temp_dir().join(format!("jl2js-{}-seekable.jsonl", std::process::id()));
+        fs::write(&path, "{}\n").unwrap();
+        assert!(InputSource::is_seekable(&path));
+        fs::remove_file(&path).unwrap();
+        assert!(!InputSource::is_seekable(&path));
+
+        // What process substitution passes: a pipe, under /dev/fd.
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        let reader = OwnedFd::from(reader);
+        let path = PathBuf::from(format!("/dev/fd/{}", reader.as_raw_fd()));
+        assert!(!InputSource::is_seekable(&path));
+
+        // Checking doesn't read from the pipe.
+        std::io::Write::write_all(&mut writer, b"{}\n").unwrap();
+        drop(writer);
+        let mut contents = String::new();
+        File::from(reader).read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "{}\n");
+    }
+}
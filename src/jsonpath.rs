@@ -0,0 +1,47 @@
+use serde_json::Value;
+use std::str::FromStr;
+
+/// A JSONPath query, in the RFC 9535 dialect, selecting values from each
+/// record. For example, `$.items[*].id` selects the id of every item and
+/// `$..book[?@.price < 10]` every book below a price, at any depth.
+#[derive(Debug, Clone)]
+pub struct JsonPath(serde_json_path::JsonPath);
+
+impl JsonPath {
+    /// The values `record` has at the path, in document order.
+    pub fn select(&self, record: &Value) -> Vec<Value> {
+        self.0.query(record).all().into_iter().cloned().collect()
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json_path::JsonPath::parse(s)
+            .map(Self)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_select() {
+        let record = json!({"items": [{"id": 1, "n": 5}, {"id": 2, "n": 20}, {"n": 30}]});
+
+        let path: JsonPath = "$.items[*].id".parse().unwrap();
+        assert_eq!(path.select(&record), [json!(1), json!(2)]);
+
+        let path: JsonPath = "$.items[?@.n > 10]".parse().unwrap();
+        assert_eq!(
+            path.select(&record),
+            [json!({"id": 2, "n": 20}), json!({"n": 30})]
+        );
+
+        assert!("$.items[".parse::<JsonPath>().is_err());
+    }
+}
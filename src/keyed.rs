@@ -0,0 +1,94 @@
+use serde_json::Value;
+
+/// Writes records as a single object, keyed by the value of a top-level
+/// field, instead of as an array.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBy {
+    pub field: String,
+    pub on_duplicate: OnDuplicate,
+}
+
+/// Writes records as a single object of arrays, grouping them by the value
+/// of a top-level field, instead of as an array.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupBy {
+    pub field: String,
+    /// The group records without a key go in. They're dropped without one.
+    pub missing: Option<String>,
+}
+
+impl GroupBy {
+    /// Returns the group `record` goes in, keyed like [`KeyBy::key_of`].
+    pub(crate) fn group_of(&self, record: &Value) -> Option<String> {
+        scalar_key(record, &self.field).or_else(|| self.missing.clone())
+    }
+}
+
+/// What to do when two records share the same key.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum OnDuplicate {
+    /// Abort the conversion.
+    #[default]
+    Error,
+    /// Keep the last record seen for the key, at the position of the first.
+    /// Records are buffered in memory until the input is exhausted.
+    KeepLast,
+}
+
+impl KeyBy {
+    /// Returns the key for `record`. Strings are used as they are, numbers
+    /// and booleans as their JSON text. Records that aren't objects or lack
+    /// a scalar value for the field have no key.
+    pub(crate) fn key_of(&self, record: &Value) -> Option<String> {
+        scalar_key(record, &self.field)
+    }
+}
+
+fn scalar_key(record: &Value, field: &str) -> Option<String> {
+    match record.get(field)? {
+        Value::String(key) => Some(key.clone()),
+        key @ (Value::Number(_) | Value::Bool(_)) => Some(key.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_key_of() {
+        let key_by = KeyBy {
+            field: "id".to_string(),
+            on_duplicate: OnDuplicate::Error,
+        };
+
+        assert_eq!(key_by.key_of(&json!({"id": "a"})), Some("a".to_string()));
+        assert_eq!(key_by.key_of(&json!({"id": 7})), Some("7".to_string()));
+        assert_eq!(key_by.key_of(&json!({"id": null})), None);
+        assert_eq!(key_by.key_of(&json!({"name": "a"})), None);
+        assert_eq!(key_by.key_of(&json!([1])), None);
+    }
+
+    #[test]
+    fn test_group_of() {
+        let mut group_by = GroupBy {
+            field: "kind".to_string(),
+            missing: None,
+        };
+
+        assert_eq!(
+            group_by.group_of(&json!({"kind": true})),
+            Some("true".to_string())
+        );
+        assert_eq!(group_by.group_of(&json!({"name": "a"})), None);
+
+        group_by.missing = Some("other".to_string());
+        assert_eq!(
+            group_by.group_of(&json!({"name": "a"})),
+            Some("other".to_string())
+        );
+    }
+}
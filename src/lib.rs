@@ -0,0 +1,2607 @@
+use convert::Converter;
+use serde::de::IgnoredAny;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+mod coerce;
+mod convert;
+mod dedup;
+mod duplicates;
+mod error;
+mod filter;
+mod infer;
+#[cfg(feature = "cli")]
+mod input;
+mod jsonpath;
+mod keyed;
+mod lines;
+#[cfg(feature = "cli")]
+mod manifest;
+mod merge;
+mod msgpack;
+#[cfg(feature = "cli")]
+mod output;
+mod reader;
+mod rows;
+mod table;
+mod transform;
+mod writer;
+mod yaml;
+
+pub use coerce::Coerce;
+pub use dedup::Dedup;
+pub use error::{ErrorLog, Jl2jsError};
+pub use filter::Filter;
+#[cfg(feature = "cli")]
+pub use input::{Compression, InputSource};
+pub use jsonpath::JsonPath;
+pub use keyed::{GroupBy, KeyBy, OnDuplicate};
+pub use lines::Delimiter;
+#[cfg(feature = "cli")]
+pub use manifest::{Checksum, Manifest};
+pub use merge::MergeArrays;
+#[cfg(feature = "cli")]
+pub use output::OutputSink;
+pub use reader::{InputFormat, JsonlReader};
+pub use writer::{ExistingArray, FloatFormat, Format, Wrap};
+
+/// Settings controlling how JSONL input is converted.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// How records are framed in the output.
+    pub format: Format,
+    /// Leave the newline off the end of the last record of
+    /// [`Format::Ndjson`] output, so newlines only separate records.
+    /// [`Format::JsonSeq`] records always end with one.
+    pub omit_final_newline: bool,
+    /// What encloses the records of [`Format::Array`] output.
+    pub wrap: Wrap,
+    /// Hold back the opening bracket until the first record is written, or
+    /// the conversion finishes without any. A conversion that fails before
+    /// then leaves nothing in the output, rather than a lone `[`.
+    pub defer_open: bool,
+    /// Append to an array that is already started in the output, rather
+    /// than writing a new one. Only applies to [`Format::Array`].
+    pub append: Option<ExistingArray>,
+    /// For CSV or TSV output, grow the header with keys first seen in later
+    /// records instead of rejecting them. All rows are buffered in memory
+    /// until the input is exhausted.
+    pub extend_header: bool,
+    /// Write records as an object keyed by a field instead. Takes
+    /// precedence over `format`.
+    pub key_by: Option<KeyBy>,
+    /// Write records as an object of arrays instead, grouped by a field.
+    /// Groups are in the order their first record was read. Since records of
+    /// different groups interleave, every record is buffered in memory until
+    /// the input is exhausted, so memory use grows with the input rather
+    /// than staying constant. `format` and `key_by` are ignored.
+    pub group_by: Option<GroupBy>,
+    /// Deep-merge every record into a single object, which is the whole
+    /// output, combining arrays as given. Records that aren't objects are an
+    /// error, or skipped when `lenient` is set. `format` and `key_by` are
+    /// ignored, and records are buffered in memory until the input is
+    /// exhausted.
+    pub merge: Option<MergeArrays>,
+    /// Write a JSON Schema inferred from the records, describing every type
+    /// seen at each place in them, instead of the records. A field is
+    /// required if every object at its place had it. `format`, `key_by` and
+    /// `merge` are ignored.
+    pub infer_schema: bool,
+    /// Pretty print each record and put one record per line.
+    pub pretty: bool,
+    /// When pretty printing, only expand objects and arrays nested this deep,
+    /// counting the record itself as depth 1, and write those inside them
+    /// compactly.
+    pub pretty_depth: Option<usize>,
+    /// When pretty printing, keep each array that holds only scalars on one
+    /// line, as in `[1, 2, 3]`, while still expanding objects and other
+    /// arrays.
+    pub compact_scalar_arrays: bool,
+    /// String used for each level of indentation when pretty printing.
+    /// Defaults to two spaces.
+    pub indent: Option<String>,
+    /// How floating point numbers are written.
+    pub float_format: FloatFormat,
+    /// Escape every character outside ASCII in strings as `\uXXXX`, with a
+    /// surrogate pair for those above U+FFFF, so the output is 7-bit clean.
+    /// Strings in CSV and TSV cells are written as they are.
+    pub ascii: bool,
+    /// Skip lines that fail to parse or aren't valid UTF-8 instead of
+    /// returning an error.
+    pub lenient: bool,
+    /// Skip lines that would be an error, as with `lenient`, and write each
+    /// one to this log along with its line number and the error.
+    pub collect_errors: Option<ErrorLog>,
+    /// Log a warning for each key of an object that appears in it more than
+    /// once, naming the line and a JSON Pointer to the key. Only the last
+    /// value of such a key is kept in the output: a parsed record can't hold
+    /// duplicates, so they can be detected but not preserved. Lines are
+    /// scanned a second time to find them. Ignored with
+    /// `whitespace_separated`.
+    pub warn_duplicate_keys: bool,
+    /// Require every record to be an object with the same set of top-level
+    /// keys as the first, in any order. Other records are an error, or
+    /// skipped when `lenient` is set.
+    pub require_uniform_keys: bool,
+    /// Parse only the first JSON value on each line and ignore whatever
+    /// follows it, even text right after it as in `{"a":1}oops` or `1x`.
+    /// Lines must still start with a complete value. Has no effect on
+    /// `relaxed` parsing.
+    pub ignore_trailing: bool,
+    /// Parse lines as JSON5, allowing comments, trailing commas, single
+    /// quotes and unquoted keys. Output is always strict JSON.
+    #[cfg(feature = "relaxed")]
+    pub relaxed: bool,
+    /// Strip whitespace from both ends of each line before parsing it. This
+    /// includes Unicode whitespace like no-break spaces, which JSON itself
+    /// doesn't allow around values. Lines left empty are skipped like blank
+    /// lines.
+    pub trim: bool,
+    /// Skip lines starting with `#` or `//`, after any whitespace, as
+    /// comments. Comments after a value on the same line are not supported.
+    pub allow_comments: bool,
+    /// How records are framed in the input. With [`InputFormat::Csv`],
+    /// `delimiter`, `max_line_bytes`, `skip_lines`, `trim`, `allow_comments`,
+    /// `ignore_trailing`, `relaxed` and `jobs` don't apply, and neither
+    /// `passthrough` nor `whitespace_separated` can be set.
+    pub input_format: InputFormat,
+    /// With CSV input, read cells that are numbers or booleans as written
+    /// in JSON, like `12` or `true`, as those instead of as strings.
+    pub csv_infer_types: bool,
+    /// Read JSON values separated by any whitespace, so that values can span
+    /// several lines, instead of one per line. `delimiter`, `max_line_bytes`,
+    /// `ignore_trailing`, `relaxed` and `jobs` don't apply. Input after a
+    /// value that fails to parse is passed over, even when `lenient` is set.
+    pub whitespace_separated: bool,
+    /// Write each line as a JSON string holding its text, without parsing
+    /// it, to turn plain text like a log into an array of strings. Blank
+    /// lines are still skipped, and `trim` and `allow_comments` still
+    /// apply. It can't be combined with CSV input, `whitespace_separated`
+    /// or `passthrough`.
+    pub lines_as_strings: bool,
+    /// What separates records in the input.
+    pub delimiter: Delimiter,
+    /// JSON Schema every record must conform to. Nonconforming records are
+    /// an error, or skipped when `lenient` is set.
+    pub schema: Option<Value>,
+    /// Maximum length of a single input line in bytes. Longer lines are an
+    /// error, or skipped when `lenient` is set. Unlimited when `None`.
+    pub max_line_bytes: Option<usize>,
+    /// Fail once more than this many records have been read, whether or not
+    /// they're written. Not even `lenient` carries on past it.
+    pub max_records: Option<usize>,
+    /// Stop at the first record that wouldn't fit in this many bytes of
+    /// output, along with what closes it, and finish the output there so
+    /// it's still complete. Bytes are counted before any compression. Only
+    /// applies to JSON formats, and can't be combined with split output;
+    /// an empty array is written however small it is.
+    pub max_output_bytes: Option<u64>,
+    /// Maximum nesting depth of a record, where a scalar is 0 deep and
+    /// `[{}]` is 2 deep. Deeper records are an error, or skipped when
+    /// `lenient` is set.
+    pub max_depth: Option<usize>,
+    /// Require every object key in a record, at any depth, to be ASCII.
+    /// Records with other keys are an error, or skipped when `lenient` is
+    /// set. Unlike `ascii`, this leaves the records unchanged.
+    pub ascii_keys_only: bool,
+    /// Write each element of a line holding an array as a record of its own.
+    /// Other lines are a single record as usual.
+    pub unwrap_arrays: bool,
+    /// Only write records matching this filter.
+    pub filter: Option<Filter>,
+    /// Keep each record that matches `filter` with this probability, from 0
+    /// to 1, deciding for each one independently. The number of records kept
+    /// is only approximately this fraction of the input.
+    pub sample: Option<f64>,
+    /// Seed for `sample`, so the same records are kept every time. Seeded
+    /// from the operating system when `None`, which needs the `cli` feature;
+    /// sampling without a seed is an error otherwise.
+    pub seed: Option<u64>,
+    /// Pass over this many records that match `filter` before writing any.
+    pub skip: usize,
+    /// Pass over this many lines at the start of each input before reading
+    /// records, as when resuming a conversion that failed partway through.
+    /// Every line counts, including blank lines, comments and ones that
+    /// aren't valid, and later lines keep their numbers in the input.
+    /// Doesn't apply with CSV input or `whitespace_separated`.
+    pub skip_lines: usize,
+    /// Stop after writing this many records. Input past that is not read.
+    pub limit: Option<usize>,
+    /// Replace each record with the value at this dotted path, like
+    /// `user.address.city` or `items.0.id` with array indices. Records
+    /// without a value at the path are dropped. Applied after `filter` and
+    /// before the other transforms.
+    pub extract: Option<String>,
+    /// Replace each record with every value this JSONPath query selects
+    /// from it, each written as a record of its own. Records it selects
+    /// nothing from are dropped. Applied after `extract`.
+    pub jsonpath: Option<JsonPath>,
+    /// Write only the first of each set of duplicate records, compared as
+    /// they are after `extract` and before the other transforms. Everything
+    /// compared by is kept in memory, so on large inputs deduplicating by a
+    /// field needs much less than by the whole record.
+    pub dedup: Option<Dedup>,
+    /// Replace strings in object records that spell numbers, booleans or
+    /// null, like `"42"` or `"true"`, with those values, after `rename` and
+    /// before the other transforms.
+    pub coerce: Coerce,
+    /// Rename keys of object records, before the other transforms. Each
+    /// pair is the key's path, which may be dotted like `user.name` to reach
+    /// into nested objects, and its new name in the same object. A record
+    /// that already has a key by the new name is an error, skipped when
+    /// lenient, unless `rename_overwrite` is set.
+    pub rename: Vec<(String, String)>,
+    /// Replace existing keys with renamed ones instead of failing.
+    pub rename_overwrite: bool,
+    /// Keep only these top-level keys of object records, in this order.
+    pub select: Option<Vec<String>>,
+    /// Remove these keys from object records. Keys may be dotted paths like
+    /// `user.token` to reach into nested objects; array indices are not
+    /// supported.
+    pub drop: Vec<String>,
+    /// Flatten nested objects and arrays in object records into top-level
+    /// keys joined by this separator, like `a.b` and `a.0`.
+    pub flatten: Option<String>,
+    /// Add these keys and values to every object record, after the other
+    /// transforms.
+    pub add_fields: Vec<(String, Value)>,
+    /// Add a key with the time each object record is converted, as an RFC
+    /// 3339 UTC timestamp like `2024-05-01T12:30:00.000Z`.
+    pub add_timestamp: Option<String>,
+    /// Add the number of the input line each record came from under this
+    /// key, after the other transforms. Records that aren't objects are
+    /// wrapped as `{key: line, "value": record}`.
+    ///
+    /// Lines are numbered from 1 in each input, counting every line,
+    /// including blank and comment lines that are skipped, so the numbers
+    /// match what an editor shows. With `whitespace_separated`, a record's
+    /// number is the line it ends on.
+    pub line_number_key: Option<String>,
+    /// Leave keys already in a record alone instead of replacing them with
+    /// `add_fields` and `add_timestamp`.
+    pub keep_existing_fields: bool,
+    /// Remove keys whose value is null from every object, including nested
+    /// ones and those inside arrays. Null array elements are kept.
+    pub drop_nulls: bool,
+    /// Replace each `\r\n` in string values with `\n`, everywhere in each
+    /// record. Object keys and lone `\r`s are left alone.
+    pub normalize_newlines: bool,
+    /// Sort the keys of every object, including nested ones, by their UTF-8
+    /// bytes. Keys otherwise keep their input order.
+    pub sort_keys: bool,
+    /// Only check that each line is valid JSON and copy it to the output as
+    /// it is, minus surrounding whitespace, instead of parsing it into a
+    /// value and serializing that again. This is much faster, but can't be
+    /// combined with options that need the parsed record, such as `pretty`,
+    /// `filter`, `select` or CSV and TSV output.
+    pub passthrough: bool,
+    /// Require every record to be an object. Anything else is an error, or
+    /// skipped when `lenient` is set.
+    pub ensure_object: bool,
+    /// Wrap records that aren't objects as `{key: record}`, so every record
+    /// is an object. This happens before the other transforms, so they
+    /// apply to wrapped records too. Objects pass through unchanged.
+    pub wrap_scalars: Option<String>,
+    /// Drop records that aren't objects when a transform that only applies
+    /// to objects, like `select` or `drop`, is in use. Otherwise they pass
+    /// through unchanged.
+    pub skip_non_objects: bool,
+    /// When an input can't be opened or fails partway through reading it,
+    /// keep the records read from it and carry on with the next input
+    /// instead of failing. [`Stats::failed_inputs`] counts these inputs.
+    /// Errors writing the output still fail the conversion.
+    pub continue_on_io_error: bool,
+    /// Stop reading input once this is set, as from a signal handler, and
+    /// finish the output as if the input had ended there, so it's still
+    /// complete JSON. It's checked before each line is read; a read that's
+    /// waiting for input isn't interrupted.
+    pub stop: Option<Arc<AtomicBool>>,
+    /// Flush the output after every line of input, so records show up as
+    /// soon as they're converted, as when reading a named pipe. Each flush
+    /// is a write system call, which costs throughput on busy inputs.
+    pub flush: bool,
+    /// Capacity of the input buffer in bytes. Defaults to 64 KiB; larger
+    /// buffers mean fewer reads on inputs with large records.
+    pub read_buffer_size: Option<usize>,
+    /// Capacity of the output buffer in bytes. Defaults to 8 KiB. Output is
+    /// written through whenever the buffer fills, so memory use is bounded
+    /// by this and the largest single record.
+    pub buffer_size: Option<usize>,
+    /// Number of threads used to parse lines. Parsing happens in parallel
+    /// batches when greater than one; output order always matches input.
+    pub jobs: usize,
+    /// Emit a deterministic minimal encoding of each record, suitable for
+    /// hashing or deduplication.
+    ///
+    /// On top of the default compact output this sorts object keys
+    /// recursively by their UTF-8 bytes. Strings are re-escaped minimally:
+    /// only `"`, `\` and control characters are escaped, and any `\uXXXX`
+    /// escape in the input is decoded to the raw character. Numbers are not
+    /// canonicalized beyond what serde_json already does (`1.0` stays a
+    /// float), Unicode is not normalized, and duplicate keys keep only their
+    /// last value. Ignored when `pretty` is set.
+    pub compact: bool,
+    /// Write each record as RFC 8785 canonical JSON (JCS), for signing
+    /// and hashing. This is stricter than `compact`: keys are sorted by
+    /// their UTF-16 code units rather than UTF-8 bytes, and every number is
+    /// written as ECMAScript writes a double. So integers never have a
+    /// fraction or exponent (`1.0` is `1`, `-0` is `0`), other numbers use
+    /// the fewest digits that read back as the same double, and exponents
+    /// are only used from `1e+21` up and below `1e-6`, as in `1e-7`.
+    /// Integers beyond 2^53 are rounded to the nearest double, as JCS
+    /// requires. `pretty`, `float_format` and `ascii` are ignored.
+    pub canonical: bool,
+}
+
+/// Counts gathered during a conversion.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Records written to the output.
+    pub records: usize,
+    /// Lines and records skipped because of an error, such as lines that
+    /// were malformed or too long, or records that didn't match the schema
+    /// or lacked the `key_by` field.
+    pub skipped: usize,
+    /// Bytes written to the output, before any compression.
+    pub bytes: u64,
+    /// Inputs given up on because they couldn't be read, with
+    /// `continue_on_io_error`.
+    pub failed_inputs: usize,
+}
+
+/// The outcome of [`check_all`].
+#[derive(Debug)]
+pub struct Report {
+    /// Lines that were valid records.
+    pub records: usize,
+    /// Lines that were not.
+    pub invalid: usize,
+    /// Why the first invalid line was rejected.
+    pub first_error: Option<Jl2jsError>,
+}
+
+/// The outcome of [`count_all`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Counts {
+    /// Lines that were valid JSON.
+    pub records: usize,
+    /// Lines that were not.
+    pub invalid: usize,
+    /// Lines that were empty or only whitespace.
+    pub blank: usize,
+    /// Bytes read from the inputs, after any decompression.
+    pub bytes: u64,
+}
+
+/// Converts JSONL read from `reader` into a JSON array written to `writer`.
+pub fn jsonl_to_json<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    pretty: bool,
+) -> Result<(), Jl2jsError> {
+    let options = Options {
+        pretty,
+        ..Options::default()
+    };
+
+    convert(reader, writer, &options)?;
+
+    Ok(())
+}
+
+/// Like [`jsonl_to_json`], but configurable through [`Options`].
+///
+/// Returns counts of what was written and skipped.
+pub fn convert<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    options: &Options,
+) -> Result<Stats, Jl2jsError> {
+    convert_all([Ok(reader)], writer, options)
+}
+
+/// Like [`convert`], but concatenates the records of several readers into
+/// a single output. Readers are consumed in order, and an `Err` aborts the
+/// conversion with that error, unless `continue_on_io_error` is set.
+///
+/// A broken pipe on `writer` ends the conversion early but successfully,
+/// since the output is no longer being read.
+pub fn convert_all<I, R, W>(readers: I, writer: W, options: &Options) -> Result<Stats, Jl2jsError>
+where
+    I: IntoIterator<Item = std::io::Result<R>>,
+    R: Read,
+    W: Write,
+{
+    run(readers, writer, options, None)
+}
+
+/// Like [`convert_all`], but also hands each record to `transform`, which
+/// may change it in place and returns whether to keep it.
+///
+/// `transform` sees records once they've passed `filter`, `sample`,
+/// `extract`, `jsonpath` and `dedup`, but before `skip` and `limit`, so
+/// records it drops aren't counted by them, and before the other
+/// transforms, like `rename` and `select`, which are applied to what it
+/// leaves. An error it returns is reported as [`Jl2jsError::Transform`]
+/// with the record's line, and is skipped like any invalid record when
+/// `lenient` or `collect_errors` is set.
+pub fn convert_with<I, R, W, F, E>(
+    readers: I,
+    writer: W,
+    options: &Options,
+    mut transform: F,
+) -> Result<Stats, Jl2jsError>
+where
+    I: IntoIterator<Item = std::io::Result<R>>,
+    R: Read,
+    W: Write,
+    F: FnMut(&mut Value) -> Result<bool, E>,
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let transform = move |value: &mut Value| transform(value).map_err(Into::into);
+
+    run(readers, writer, options, Some(Box::new(transform)))
+}
+
+fn run<I, R, W>(
+    readers: I,
+    writer: W,
+    options: &Options,
+    transform: Option<convert::Transform<'_>>,
+) -> Result<Stats, Jl2jsError>
+where
+    I: IntoIterator<Item = std::io::Result<R>>,
+    R: Read,
+    W: Write,
+{
+    let writer = match options.buffer_size {
+        Some(capacity) => BufWriter::with_capacity(capacity, writer),
+        None => BufWriter::new(writer),
+    };
+
+    let mut converter = Converter::new(writer, options)?;
+
+    if let Some(transform) = transform {
+        converter = converter.with_transform(transform);
+    }
+
+    let result = readers
+        .into_iter()
+        .try_for_each(|reader| converter.convert_input(reader))
+        .and_then(|()| converter.finish());
+
+    match result {
+        // Whatever reads the output stopped early, as in `jl2js | head`.
+        // There's no one left to report the truncated output to.
+        Err(Jl2jsError::Io(e)) if e.kind() == ErrorKind::BrokenPipe => Ok(converter.stats()),
+        result => result.map(|()| converter.stats()),
+    }
+}
+
+/// Like [`convert_all`], but splits the output into chunks of at most
+/// `records_per_chunk` records, each complete on its own, such as a JSON
+/// array. `open` is called with the index of each chunk, from 0, to get the
+/// writer for it; a chunk is only opened once it has a record to hold, or
+/// for chunk 0, which is always written.
+///
+/// None of `append`, `key_by`, `group_by`, `merge`, `infer_schema` or
+/// `max_output_bytes` can be used with split output.
+pub fn convert_chunks<I, R, W, F>(
+    readers: I,
+    mut open: F,
+    records_per_chunk: usize,
+    options: &Options,
+) -> Result<Stats, Jl2jsError>
+where
+    I: IntoIterator<Item = std::io::Result<R>>,
+    R: Read,
+    W: Write,
+    F: FnMut(usize) -> std::io::Result<W>,
+{
+    if records_per_chunk == 0
+        || options.append.is_some()
+        || options.key_by.is_some()
+        || options.group_by.is_some()
+        || options.merge.is_some()
+        || options.infer_schema
+        || options.max_output_bytes.is_some()
+    {
+        return Err(Jl2jsError::Usage(
+            "output can only be split into chunks of at least one record, without append, key_by, group_by, merge, infer_schema or max_output_bytes"
+                .to_string(),
+        ));
+    }
+
+    let mut buffered = |index| {
+        let writer = open(index)?;
+
+        Ok(match options.buffer_size {
+            Some(capacity) => BufWriter::with_capacity(capacity, writer),
+            None => BufWriter::new(writer),
+        })
+    };
+
+    let mut converter = Converter::new(buffered(0)?, options)?.split(records_per_chunk, buffered);
+
+    for reader in readers {
+        converter.convert_input(reader)?;
+    }
+
+    converter.finish()?;
+
+    Ok(converter.stats())
+}
+
+/// Checks the records of `readers` as [`convert_all`] would, without writing
+/// anything. Every line is read, collecting problems with individual lines
+/// into the report, as if `options.lenient` were set; errors reading input
+/// are returned.
+pub fn check_all<I, R>(readers: I, options: &Options) -> Result<Report, Jl2jsError>
+where
+    I: IntoIterator<Item = std::io::Result<R>>,
+    R: Read,
+{
+    // Nothing is written, so records are checked as JSON rather than
+    // against a CSV header or an existing array.
+    let options = Options {
+        format: Format::Ndjson,
+        append: None,
+        extend_header: false,
+        lenient: true,
+        ..options.clone()
+    };
+
+    let mut converter = Converter::new(std::io::sink(), &options)?;
+
+    for reader in readers {
+        converter.convert_input(reader)?;
+    }
+
+    converter.finish()?;
+    let stats = converter.stats();
+
+    Ok(Report {
+        records: stats.records,
+        invalid: stats.skipped,
+        first_error: converter.take_first_error(),
+    })
+}
+
+/// Counts the lines of `readers`, without building or writing any records,
+/// which is cheaper than [`check_all`]. Lines are only checked to be valid
+/// JSON, so of [`Options`] only those about reading and parsing lines apply,
+/// and the input must be JSONL. Errors reading an input are returned unless
+/// `options.continue_on_io_error` is set, in which case what was read of it
+/// is counted.
+pub fn count_all<I, R>(readers: I, options: &Options) -> Result<Counts, Jl2jsError>
+where
+    I: IntoIterator<Item = std::io::Result<R>>,
+    R: Read,
+{
+    if options.input_format != InputFormat::Jsonl {
+        let message = "only JSONL input can be counted";
+        return Err(Jl2jsError::Usage(message.to_string()));
+    }
+
+    let mut counts = Counts::default();
+
+    for reader in readers {
+        let reader = match reader {
+            Ok(reader) => reader,
+            Err(_) if options.continue_on_io_error => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let mut reader = JsonlReader::with_options(reader, options);
+
+        while let Some(line) = reader.next_line() {
+            match line {
+                Ok((_, line)) => match reader::parse_line::<IgnoredAny>(&line, options) {
+                    Ok(_) => counts.records += 1,
+                    Err(_) => counts.invalid += 1,
+                },
+                Err(Jl2jsError::Io(_)) if options.continue_on_io_error => break,
+                Err(Jl2jsError::Io(e)) => return Err(e.into()),
+                Err(_) => counts.invalid += 1,
+            }
+        }
+
+        counts.blank += reader.blank_lines();
+        counts.bytes += reader.bytes();
+    }
+
+    Ok(counts)
+}
+
+/// Converts a JSON array read from `reader` into JSONL written to `writer`.
+///
+/// Each element of the array is written as a compact JSON document on its own line.
+/// The last line ends with a newline only if `final_newline` is set.
+pub fn json_to_jsonl<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    final_newline: bool,
+) -> Result<(), Jl2jsError> {
+    let reader = BufReader::new(reader);
+    let mut writer = BufWriter::new(writer);
+
+    let records = match serde_json::from_reader(reader) {
+        Ok(Value::Array(records)) => records,
+        Ok(value) => {
+            return Err(Jl2jsError::NotAnArray {
+                found: error::type_name(&value),
+            })
+        }
+        Err(e) if e.is_io() => return Err(Jl2jsError::Io(e.into())),
+        Err(source) => {
+            return Err(Jl2jsError::Parse {
+                line: source.line(),
+                source,
+            })
+        }
+    };
+
+    for (index, record) in records.iter().enumerate() {
+        if index > 0 {
+            writer.write_all(b"\n")?;
+        }
+
+        serde_json::to_writer(&mut writer, record).map_err(error::serialize_error)?;
+    }
+
+    if final_newline && !records.is_empty() {
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(writer.flush()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn lenient() -> Options {
+        Options {
+            lenient: true,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn test_process() {
+        let input = r#"{"foo": "bar"}
+{"foo": "baz"}"#;
+
+        let mut output = Vec::new();
+        jsonl_to_json(Cursor::new(input), &mut output, false).unwrap();
+
+        let expected_output = b"[{\"foo\":\"bar\"},{\"foo\":\"baz\"}]";
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    fn test_process_pretty() {
+        let input = r#"{"foo": "bar"}
+{"foo": "baz"}"#;
+
+        let mut output = Vec::new();
+        jsonl_to_json(Cursor::new(input), &mut output, true).unwrap();
+
+        let expected_output = b"[\n{\n  \"foo\": \"bar\"\n},\n{\n  \"foo\": \"baz\"\n}\n]";
+
+        assert_eq!(output, expected_output,);
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        let input = r#"{"foo": "bar"}{"foo": "baz"#; // Malformed JSON
+
+        let mut output = Vec::new();
+        let result = jsonl_to_json(Cursor::new(input), &mut output, false);
+
+        assert!(result.is_err(), "Process should error on invalid JSON");
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let input = "";
+
+        let mut output = Vec::new();
+        let result = jsonl_to_json(Cursor::new(input), &mut output, false);
+
+        assert!(
+            result.is_ok(),
+            "Process should handle empty input without error"
+        );
+        assert_eq!(
+            output, b"[]",
+            "Output should be an empty JSON array for empty input"
+        );
+    }
+
+    #[test]
+    fn test_lenient_skips_malformed_lines() {
+        let input = r#"{"foo": "bar"}
+{"foo": "baz"
+{"foo": "qux"}
+not json"#;
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &lenient()).unwrap();
+
+        assert_eq!(
+            stats,
+            Stats {
+                records: 2,
+                skipped: 2,
+                bytes: output.len() as u64,
+                failed_inputs: 0,
+            }
+        );
+        assert_eq!(output, b"[{\"foo\":\"bar\"},{\"foo\":\"qux\"}]");
+    }
+
+    #[test]
+    fn test_lenient_all_skipped() {
+        let input = "nope\n{oops";
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &lenient()).unwrap();
+
+        assert_eq!(stats.records, 0);
+        assert_eq!(stats.skipped, 2);
+        assert_eq!(output, b"[]");
+    }
+
+    #[test]
+    fn test_blank_lines_skipped() {
+        let input = "{\"foo\": \"bar\"}\n   \n{\"foo\": \"baz\"}\n\n";
+
+        let mut output = Vec::new();
+        jsonl_to_json(Cursor::new(input), &mut output, false).unwrap();
+
+        assert_eq!(output, b"[{\"foo\":\"bar\"},{\"foo\":\"baz\"}]");
+    }
+
+    #[test]
+    fn test_json_to_jsonl_round_trip() {
+        let input = "{\"foo\":\"bar\"}\n[1,2]\n42\n";
+
+        let mut json = Vec::new();
+        jsonl_to_json(Cursor::new(input), &mut json, true).unwrap();
+
+        let mut jsonl = Vec::new();
+        json_to_jsonl(Cursor::new(&json), &mut jsonl, true).unwrap();
+        assert_eq!(jsonl, input.as_bytes());
+
+        let mut jsonl = Vec::new();
+        json_to_jsonl(Cursor::new(&json), &mut jsonl, false).unwrap();
+        assert_eq!(jsonl, input.trim_end().as_bytes());
+
+        let mut jsonl = Vec::new();
+        json_to_jsonl(Cursor::new("[]"), &mut jsonl, true).unwrap();
+        assert!(jsonl.is_empty());
+    }
+
+    #[test]
+    fn test_json_to_jsonl_requires_array() {
+        let mut output = Vec::new();
+        let result = json_to_jsonl(Cursor::new(r#"{"foo": "bar"}"#), &mut output, true);
+
+        assert!(
+            matches!(result, Err(Jl2jsError::NotAnArray { found: "an object" })),
+            "Reverse mode should error on non-array input"
+        );
+
+        let result = json_to_jsonl(Cursor::new("[1,\n2,]"), &mut output, true);
+        assert!(matches!(result, Err(Jl2jsError::Parse { line: 2, .. })));
+    }
+
+    #[test]
+    fn test_parse_error_reports_line_number() {
+        let input = "{\"foo\": \"bar\"}\n\n{\"foo\": }";
+
+        let mut output = Vec::new();
+        let err = convert(Cursor::new(input), &mut output, &Options::default()).unwrap_err();
+
+        assert!(matches!(err, Jl2jsError::Parse { line: 3, .. }));
+        assert_eq!(err.to_string(), "line 3: expected value at column 9");
+    }
+
+    #[test]
+    fn test_compact_normalizes_records() {
+        let input = "{ \"b\" : \"\\u0041\\u00e9\", \"a\" : { \"d\": 1, \"c\": [ 2 ] } }";
+        let options = Options {
+            compact: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "[{\"a\":{\"c\":[2],\"d\":1},\"b\":\"A\u{e9}\"}]"
+        );
+    }
+
+    #[test]
+    fn test_key_order_preserved() {
+        let input = r#"{"zulu": 1, "mike": 2, "alpha": 3}"#;
+
+        let mut output = Vec::new();
+        jsonl_to_json(Cursor::new(input), &mut output, false).unwrap();
+
+        assert_eq!(output, b"[{\"zulu\":1,\"mike\":2,\"alpha\":3}]");
+    }
+
+    #[test]
+    fn test_ndjson_output() {
+        let input = "{\"foo\": \"bar\"}\n\n{\"foo\": [1, 2]}";
+        let options = Options {
+            format: Format::Ndjson,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(output, b"{\"foo\":\"bar\"}\n{\"foo\":[1,2]}\n");
+    }
+
+    #[test]
+    fn test_ndjson_output_pretty() {
+        let input = "{\"foo\": \"bar\"}\n{\"foo\": \"baz\"}";
+        let options = Options {
+            format: Format::Ndjson,
+            pretty: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(
+            output,
+            b"{\n  \"foo\": \"bar\"\n}\n{\n  \"foo\": \"baz\"\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_custom_indent() {
+        let input = r#"{"foo": {"bar": 1}}"#;
+        let options = Options {
+            pretty: true,
+            indent: Some("\t".to_string()),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(output, b"[\n{\n\t\"foo\": {\n\t\t\"bar\": 1\n\t}\n}\n]");
+    }
+
+    #[test]
+    fn test_max_line_bytes() {
+        let input = "{\"foo\": \"bar\"}\n{\"foo\": \"a much longer value\"}\n{\"foo\": 1}";
+        let mut options = Options {
+            max_line_bytes: Some(16),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        let err = convert(Cursor::new(input), &mut output, &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::LineTooLong { line: 2, max: 16 }));
+
+        options.lenient = true;
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(output, b"[{\"foo\":\"bar\"},{\"foo\":1}]");
+    }
+
+    #[test]
+    fn test_filter_keeps_matching_records() {
+        let input = r#"{"status": "active", "id": 1}
+{"status": "inactive", "id": 2}
+{"id": 3}
+{"status": "active", "id": 4}
+{"status": "inactive", "id": 5}"#;
+        let options = Options {
+            filter: Some("status=active".parse().unwrap()),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(
+            output,
+            b"[{\"status\":\"active\",\"id\":1},{\"status\":\"active\",\"id\":4}]"
+        );
+    }
+
+    #[test]
+    fn test_parallel_preserves_order() {
+        let input: String = (0..5000).map(|i| format!("{{\"id\": {}}}\n", i)).collect();
+        let options = Options {
+            jobs: 4,
+            ..Options::default()
+        };
+
+        let mut parallel = Vec::new();
+        convert(Cursor::new(&input), &mut parallel, &options).unwrap();
+
+        let mut sequential = Vec::new();
+        jsonl_to_json(Cursor::new(&input), &mut sequential, false).unwrap();
+
+        assert_eq!(parallel, sequential);
+    }
+
+    #[test]
+    fn test_convert_all_spans_readers() {
+        // The first reader has no trailing newline, which must not merge its
+        // last line with the first line of the next reader.
+        let readers = [
+            Ok(Cursor::new("{\"a\": 1}\n{\"a\": 2}")),
+            Ok(Cursor::new("")),
+            Ok(Cursor::new("{\"a\": 3}\n")),
+        ];
+
+        let mut output = Vec::new();
+        convert_all(readers, &mut output, &Options::default()).unwrap();
+
+        assert_eq!(output, b"[{\"a\":1},{\"a\":2},{\"a\":3}]");
+    }
+
+    #[test]
+    fn test_key_by() {
+        let input = r#"{"id": "a", "n": 1}
+{"id": "b", "n": 2}
+{"id": "a", "n": 3}"#;
+        let mut options = Options {
+            key_by: Some(KeyBy {
+                field: "id".to_string(),
+                on_duplicate: OnDuplicate::KeepLast,
+            }),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(stats.records, 2);
+        assert_eq!(
+            output,
+            b"{\"a\":{\"id\":\"a\",\"n\":3},\"b\":{\"id\":\"b\",\"n\":2}}"
+        );
+
+        options.key_by.as_mut().unwrap().on_duplicate = OnDuplicate::Error;
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::DuplicateKey { line: 3, .. }));
+    }
+
+    #[test]
+    fn test_key_by_missing_field() {
+        let input = "{\"id\": 1}\n{\"name\": \"x\"}";
+        let mut options = Options {
+            key_by: Some(KeyBy {
+                field: "id".to_string(),
+                on_duplicate: OnDuplicate::Error,
+            }),
+            ..Options::default()
+        };
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::MissingKey { line: 2, .. }));
+
+        options.lenient = true;
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(output, b"{\"1\":{\"id\":1}}");
+    }
+
+    #[test]
+    fn test_group_by() {
+        let input = r#"{"kind": "a", "n": 1}
+{"kind": "b", "n": 2}
+{"n": 3}
+{"kind": "a", "n": 4}
+{"kind": "b", "n": 5}"#;
+        let mut options = Options {
+            group_by: Some(GroupBy {
+                field: "kind".to_string(),
+                missing: None,
+            }),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"{"a":[{"kind":"a","n":1},{"kind":"a","n":4}],"b":[{"kind":"b","n":2},{"kind":"b","n":5}]}"#
+        );
+
+        options.group_by.as_mut().unwrap().missing = Some("none".to_string());
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"{"a":[{"kind":"a","n":1},{"kind":"a","n":4}],"b":[{"kind":"b","n":2},{"kind":"b","n":5}],"none":[{"n":3}]}"#
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        let input = "{\"a\": 1, \"b\": 2, \"c\": 3, \"d\": 4}\n[1, 2]";
+        let mut options = Options {
+            select: Some(vec!["b".to_string(), "d".to_string(), "e".to_string()]),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"[{\"b\":2,\"d\":4},[1,2]]");
+
+        options.skip_non_objects = true;
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"[{\"b\":2,\"d\":4}]");
+    }
+
+    #[test]
+    fn test_drop() {
+        let input = r#"{"user": {"name": "n", "token": "t"}, "password": "p", "id": 1}"#;
+        let options = Options {
+            drop: vec!["password".to_string(), "user.token".to_string()],
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(output, b"[{\"user\":{\"name\":\"n\"},\"id\":1}]");
+    }
+
+    #[test]
+    fn test_csv_input() {
+        let input = "name,age\nann,41\n\"lee, jr\",007\n";
+        let mut options = Options {
+            input_format: InputFormat::Csv,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            output,
+            br#"[{"name":"ann","age":"41"},{"name":"lee, jr","age":"007"}]"#
+        );
+
+        options.csv_infer_types = true;
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            output,
+            br#"[{"name":"ann","age":41},{"name":"lee, jr","age":"007"}]"#
+        );
+
+        let err = convert(Cursor::new("a,b\n1,2\n3\n"), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::InvalidCsv { line: 3, .. }));
+    }
+
+    #[test]
+    fn test_rename() {
+        let input = concat!(
+            "{\"id\": 1, \"user\": {\"name\": \"a\"}, \"ts\": 5}\n",
+            "{\"id\": 2, \"user\": {\"name\": \"b\", \"login\": \"x\"}}\n",
+        );
+        let mut options = Options {
+            rename: vec![
+                ("ts".to_string(), "time".to_string()),
+                ("user.name".to_string(), "login".to_string()),
+            ],
+            ..Options::default()
+        };
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::RenameConflict { line: 2, .. }));
+
+        options.lenient = true;
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, br#"[{"id":1,"user":{"login":"a"},"time":5}]"#);
+
+        options.rename_overwrite = true;
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            output,
+            br#"[{"id":1,"user":{"login":"a"},"time":5},{"id":2,"user":{"login":"b"}}]"#
+        );
+    }
+
+    #[test]
+    fn test_output_is_streamed() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct CountingReader<R> {
+            inner: R,
+            read: Rc<Cell<usize>>,
+        }
+
+        impl<R: Read> Read for CountingReader<R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = self.inner.read(buf)?;
+                self.read.set(self.read.get() + n);
+                Ok(n)
+            }
+        }
+
+        #[derive(Default)]
+        struct RecordingWriter {
+            read: Rc<Cell<usize>>,
+            read_at_first_write: Option<usize>,
+            writes: usize,
+            largest_write: usize,
+        }
+
+        impl Write for RecordingWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.read_at_first_write.get_or_insert(self.read.get());
+                self.writes += 1;
+                self.largest_write = self.largest_write.max(buf.len());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let input: String = (0..100_000)
+            .map(|i| format!("{{\"id\": {}}}\n", i))
+            .collect();
+        let read = Rc::new(Cell::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new(input.as_bytes()),
+            read: read.clone(),
+        };
+        let mut writer = RecordingWriter {
+            read,
+            ..RecordingWriter::default()
+        };
+        let options = Options {
+            read_buffer_size: Some(8192),
+            buffer_size: Some(4096),
+            ..Options::default()
+        };
+
+        convert(reader, &mut writer, &options).unwrap();
+
+        assert!(writer.read_at_first_write.unwrap() < input.len() / 100);
+        assert!(writer.writes > 100);
+        assert!(writer.largest_write <= 4096);
+    }
+
+    #[test]
+    fn test_leading_bom_stripped() {
+        let input = b"\xEF\xBB\xBF{\"foo\": \"bar\"}\n{\"foo\": \"baz\"}";
+
+        let mut output = Vec::new();
+        jsonl_to_json(Cursor::new(input), &mut output, false).unwrap();
+
+        assert_eq!(output, b"[{\"foo\":\"bar\"},{\"foo\":\"baz\"}]");
+    }
+
+    #[cfg(feature = "relaxed")]
+    #[test]
+    fn test_relaxed_input() {
+        let input = "{'foo': 'bar', baz: [1, 2,],} // trailing comment\n{\"strict\": true}";
+        let options = Options {
+            relaxed: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(
+            output,
+            b"[{\"foo\":\"bar\",\"baz\":[1,2]},{\"strict\":true}]"
+        );
+    }
+
+    #[test]
+    fn test_crlf_delimiter_matches_lf() {
+        let lf = "{\"foo\": \"bar\"}\n{\"foo\": \"baz\"}\n";
+        let crlf = "{\"foo\": \"bar\"}\r\n{\"foo\": \"baz\"}\r\n";
+        let options = Options {
+            delimiter: Delimiter::Crlf,
+            ..Options::default()
+        };
+
+        let mut expected = Vec::new();
+        jsonl_to_json(Cursor::new(lf), &mut expected, false).unwrap();
+
+        let mut output = Vec::new();
+        convert(Cursor::new(crlf), &mut output, &options).unwrap();
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_json_seq_output() {
+        let input = "{\"foo\": \"bar\"}\n[1, 2]";
+        let options = Options {
+            format: Format::JsonSeq,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(output, b"\x1e{\"foo\":\"bar\"}\n\x1e[1,2]\n");
+    }
+
+    #[test]
+    fn test_schema_validation() {
+        let input = "{\"id\": 1}\n{\"id\": \"two\"}\n{\"id\": 3}";
+        let mut options = Options {
+            schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {"id": {"type": "integer"}},
+                "required": ["id"]
+            })),
+            ..Options::default()
+        };
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(
+            matches!(&err, Jl2jsError::SchemaViolation { line: 2, path, .. } if path == "/id"),
+            "unexpected error: {}",
+            err
+        );
+
+        options.lenient = true;
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(output, b"[{\"id\":1},{\"id\":3}]");
+    }
+
+    #[test]
+    fn test_flatten() {
+        let input = r#"{"user": {"name": "n", "address": {"city": "c"}}, "tags": ["x", "y"]}"#;
+        let options = Options {
+            flatten: Some(".".to_string()),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(
+            output,
+            b"[{\"user.name\":\"n\",\"user.address.city\":\"c\",\"tags.0\":\"x\",\"tags.1\":\"y\"}]"
+        );
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let input = r#"{"id": 1, "name": "a, b", "tags": ["x"]}
+{"name": "say \"hi\"", "id": 2}
+{"id": 3, "extra": true}"#;
+        let mut options = Options {
+            format: Format::Csv,
+            ..Options::default()
+        };
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::UnexpectedKey { line: 3, .. }));
+
+        options.extend_header = true;
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "id,name,tags,extra\n\
+             1,\"a, b\",\"[\"\"x\"\"]\",\n\
+             2,\"say \"\"hi\"\"\",,\n\
+             3,,,true\n"
+        );
+    }
+
+    /// Accepts `remaining` bytes, then fails like a closed pipe.
+    struct ClosedPipe {
+        remaining: usize,
+    }
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.remaining == 0 {
+                return Err(ErrorKind::BrokenPipe.into());
+            }
+
+            let written = buf.len().min(self.remaining);
+            self.remaining -= written;
+
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_broken_pipe_stops_cleanly() {
+        let input = "{\"a\":1}\n{\"a\":2}\n{\"a\":3}\n";
+        let options = Options {
+            buffer_size: Some(1),
+            ..Options::default()
+        };
+
+        let stats = convert(Cursor::new(input), ClosedPipe { remaining: 8 }, &options).unwrap();
+        assert_eq!(stats.bytes, 8);
+    }
+
+    #[test]
+    fn test_append() {
+        let input = "{\"a\":1}\n{\"a\":2}";
+
+        for (append, expected) in [
+            (ExistingArray::Empty, "{\"a\":1},{\"a\":2}]"),
+            (ExistingArray::NonEmpty, ",{\"a\":1},{\"a\":2}]"),
+        ] {
+            let options = Options {
+                append: Some(append),
+                ..Options::default()
+            };
+
+            let mut output = Vec::new();
+            convert(Cursor::new(input), &mut output, &options).unwrap();
+
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let input = "{\"a\":1}\nnot json\n\n[1,]\n{\"a\":2}\n";
+
+        let report = check_all([Ok(Cursor::new(input))], &Options::default()).unwrap();
+
+        assert_eq!(report.records, 2);
+        assert_eq!(report.invalid, 2);
+        assert!(matches!(
+            report.first_error,
+            Some(Jl2jsError::Parse { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_skip_and_limit() {
+        let input = "1\n2\n3\n4\n";
+
+        for (skip, limit, expected) in [
+            (0, Some(2), "[1,2]"),
+            (0, Some(10), "[1,2,3,4]"),
+            (0, Some(0), "[]"),
+            (1, Some(2), "[2,3]"),
+            (3, None, "[4]"),
+        ] {
+            let options = Options {
+                skip,
+                limit,
+                ..Options::default()
+            };
+
+            let mut output = Vec::new();
+            convert(Cursor::new(input), &mut output, &options).unwrap();
+
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_sample() {
+        let input: String = (0..1000).map(|n| format!("{}\n", n)).collect();
+        let sample = |seed| {
+            let options = Options {
+                sample: Some(0.1),
+                seed: Some(seed),
+                ..Options::default()
+            };
+
+            let mut output = Vec::new();
+            let stats = convert(Cursor::new(&input), &mut output, &options).unwrap();
+            assert!((50..150).contains(&stats.records));
+
+            output
+        };
+
+        assert_eq!(sample(1), sample(1));
+        assert_ne!(sample(1), sample(2));
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let input = r#"{"b": {"z": 1, "y": [{"d": 1, "c": 2}]}, "a": null}"#;
+        let options = Options {
+            sort_keys: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[{"a":null,"b":{"y":[{"c":2,"d":1}],"z":1}}]"#
+        );
+    }
+
+    #[test]
+    fn test_unwrap_arrays() {
+        let input = "[1, 2]\n{\"a\": [3]}\n[]\n[[4], {\"b\": 5}]\n";
+        let options = Options {
+            unwrap_arrays: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[1,2,{"a":[3]},[4],{"b":5}]"#
+        );
+        assert_eq!(stats.records, 5);
+    }
+
+    #[cfg(feature = "arbitrary-precision")]
+    #[test]
+    fn test_numbers_are_preserved() {
+        let input = r#"{"big": 1234567890123456789012345, "exact": 0.10000000000000000000001, "exp": 1e+2}"#;
+
+        let mut output = Vec::new();
+        jsonl_to_json(Cursor::new(input), &mut output, false).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[{"big":1234567890123456789012345,"exact":0.10000000000000000000001,"exp":1e+2}]"#
+        );
+    }
+
+    #[test]
+    fn test_convert_chunks() {
+        let dir = std::env::temp_dir().join(format!("jl2js-{}-chunks", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |index| dir.join(format!("out-{}.json", index));
+
+        let input = "1\n2\n3\n4\n5\n";
+        let stats = convert_chunks(
+            [Ok(Cursor::new(input))],
+            |index| std::fs::File::create(path(index)),
+            3,
+            &Options::default(),
+        )
+        .unwrap();
+
+        assert_eq!(stats.records, 5);
+        assert_eq!(std::fs::read_to_string(path(0)).unwrap(), "[1,2,3]");
+        assert_eq!(std::fs::read_to_string(path(1)).unwrap(), "[4,5]");
+        assert!(!path(2).exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_wrap() {
+        let input = "1\n2\n";
+
+        for (wrap, expected) in [
+            ("none", "1,2"),
+            ("array", "[1,2]"),
+            ("field:records", r#"{"records":[1,2]}"#),
+        ] {
+            let options = Options {
+                wrap: wrap.parse().unwrap(),
+                ..Options::default()
+            };
+
+            let mut output = Vec::new();
+            convert(Cursor::new(input), &mut output, &options).unwrap();
+
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+        }
+
+        assert!("field:".parse::<Wrap>().is_err());
+    }
+
+    #[test]
+    fn test_ignore_trailing() {
+        let input = "{\"a\":1} oops\n[2]trailing text\n3x\n\"s\"\n";
+        let mut options = Options::default();
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::Parse { line: 1, .. }));
+
+        options.ignore_trailing = true;
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), r#"[{"a":1},[2],3,"s"]"#);
+    }
+
+    #[test]
+    fn test_whitespace_separated() {
+        let input = "{\"a\":1}\n{\n  \"b\": 2\n}\n{\"c\":3} 4\n";
+        let options = Options {
+            whitespace_separated: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[{"a":1},{"b":2},{"c":3},4]"#
+        );
+
+        let err = convert(Cursor::new("1\n\n{]"), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::Parse { line: 3, .. }));
+    }
+
+    #[test]
+    fn test_ensure_object() {
+        let input = "{\"a\":1}\n42\n{\"b\":2}";
+        let mut options = Options {
+            ensure_object: true,
+            ..Options::default()
+        };
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(
+            err,
+            Jl2jsError::NotAnObject {
+                line: 2,
+                found: "a number"
+            }
+        ));
+
+        options.lenient = true;
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(output, b"[{\"a\":1},{\"b\":2}]");
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let input = "{\"b\": 1,   \"a\": 2.50}\n  [1,2]  \n{oops}\n";
+        let mut options = Options {
+            passthrough: true,
+            lenient: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[{"b": 1,   "a": 2.50},[1,2]]"#
+        );
+
+        options.pretty = true;
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::Usage(_)));
+    }
+
+    #[test]
+    fn test_trim() {
+        let input = "  {\"a\": 1}\t\n\u{a0}[2]\u{3000}\n \u{a0} \n\t3  ";
+        let mut options = Options::default();
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::Parse { line: 2, .. }));
+
+        options.trim = true;
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"[{\"a\":1},[2],3]");
+    }
+
+    #[test]
+    fn test_allow_comments() {
+        let input = "# fixtures\n{\"a\":1}\n  // disabled: {\"a\":2}\n{\"a\":3}\n#";
+        let mut options = Options::default();
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::Parse { line: 1, .. }));
+
+        options.allow_comments = true;
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(stats.skipped, 0);
+        assert_eq!(output, b"[{\"a\":1},{\"a\":3}]");
+    }
+
+    #[test]
+    fn test_merge() {
+        let input = concat!(
+            r#"{"server": {"host": "localhost", "ports": [80]}, "debug": true}"#,
+            "\n",
+            r#"{"server": {"ports": [443], "tls": {"enabled": true}}, "debug": false}"#,
+        );
+        let mut options = Options {
+            merge: Some(MergeArrays::Replace),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"{"server":{"host":"localhost","ports":[443],"tls":{"enabled":true}},"debug":false}"#
+        );
+
+        options.merge = Some(MergeArrays::Concat);
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains(r#""ports":[80,443]"#));
+
+        let err = convert(Cursor::new("{}\n[]"), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::NotAnObject { line: 2, .. }));
+    }
+
+    #[test]
+    fn test_extract() {
+        let input = "{\"items\":[{\"id\":1}]}\n{\"items\":[]}\n{\"items\":[{\"id\":{\"n\":2}}]}";
+        let options = Options {
+            extract: Some("items.0.id".to_string()),
+            limit: Some(2),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"[1,{\"n\":2}]");
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let input = b"{\"a\":1}\n\xc3\x28\n{\"a\":2}";
+        let mut options = Options::default();
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: invalid UTF-8 at column 1");
+
+        options.lenient = true;
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(output, b"[{\"a\":1},{\"a\":2}]");
+    }
+
+    #[test]
+    fn test_drop_nulls() {
+        let input = "{\"a\":null,\"b\":{\"c\":null,\"d\":[null,{\"e\":null}]}}\nnull";
+        let options = Options {
+            drop_nulls: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"[{\"b\":{\"d\":[null,{}]}},null]");
+    }
+
+    #[test]
+    fn test_dedup() {
+        let input = concat!(
+            "{\"id\":1,\"v\":\"a\"}\n",
+            "{\"v\":\"a\",\"id\":1}\n",
+            "{\"id\":1,\"v\":\"b\"}\n",
+            "{\"v\":\"c\"}\n",
+            "{\"v\":\"c\"}\n",
+        );
+        let mut options = Options {
+            dedup: Some(Dedup::Record),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[{"id":1,"v":"a"},{"id":1,"v":"b"},{"v":"c"}]"#
+        );
+
+        options.dedup = Some(Dedup::Field("id".to_string()));
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[{"id":1,"v":"a"},{"v":"c"},{"v":"c"}]"#
+        );
+    }
+
+    #[test]
+    fn test_add_fields() {
+        let input = "{\"id\":1}\n{\"id\":2,\"env\":\"dev\"}\n[3]";
+        let options = Options {
+            add_fields: vec![("env".to_string(), Value::from("prod"))],
+            add_timestamp: Some("converted_at".to_string()),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        let records: Vec<Value> = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(records.len(), 3);
+        for record in &records[..2] {
+            assert_eq!(record["env"], "prod");
+            assert!(record["converted_at"].as_str().unwrap().ends_with('Z'));
+        }
+        assert_eq!(records[2], serde_json::json!([3]));
+    }
+
+    #[test]
+    fn test_pretty_depth() {
+        let input = r#"{"a": {"b": {"c": 1}}, "d": [1, [2]], "e": {}}"#;
+        let mut options = Options {
+            format: Format::Ndjson,
+            pretty: true,
+            pretty_depth: Some(1),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\n  \"a\": {\"b\":{\"c\":1}},\n  \"d\": [1,[2]],\n  \"e\": {}\n}\n"
+        );
+
+        // Deep enough to expand everything, it's the same as plain pretty
+        // printing.
+        let pretty = |options: &Options| {
+            let mut output = Vec::new();
+            convert(Cursor::new(input), &mut output, options).unwrap();
+            output
+        };
+        let expanded = pretty(&Options {
+            pretty_depth: Some(3),
+            ..options.clone()
+        });
+        options.pretty_depth = None;
+        assert_eq!(expanded, pretty(&options));
+    }
+
+    #[test]
+    fn test_continue_on_io_error() {
+        struct Failing;
+
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk on fire"))
+            }
+        }
+
+        let readers = || -> Vec<std::io::Result<Box<dyn Read>>> {
+            vec![
+                Ok(Box::new(Cursor::new("1\n"))),
+                Err(std::io::Error::from(ErrorKind::NotFound)),
+                Ok(Box::new(Failing)),
+                Ok(Box::new(Cursor::new("2\n"))),
+            ]
+        };
+        let mut options = Options::default();
+
+        let err = convert_all(readers(), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::Io(e) if e.kind() == ErrorKind::NotFound));
+
+        options.continue_on_io_error = true;
+
+        let mut output = Vec::new();
+        let stats = convert_all(readers(), &mut output, &options).unwrap();
+        assert_eq!(stats.failed_inputs, 2);
+        assert_eq!(output, b"[1,2]");
+    }
+
+    #[test]
+    fn test_line_number_key() {
+        let input = "{\"a\":1}\n\n   \n\"two\"\n{\"a\":3,\"line\":0}\n";
+        let options = Options {
+            line_number_key: Some("line".to_string()),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[{"a":1,"line":1},{"line":4,"value":"two"},{"a":3,"line":5}]"#
+        );
+    }
+
+    #[test]
+    fn test_collect_errors() {
+        #[derive(Clone, Default)]
+        struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let errors = Shared::default();
+        let options = Options {
+            collect_errors: Some(ErrorLog::new(errors.clone())),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        let stats = convert(
+            Cursor::new("{\"a\":1}\n{oops\n{\"a\":2}\n"),
+            &mut output,
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(output, b"[{\"a\":1},{\"a\":2}]");
+        assert_eq!(
+            String::from_utf8(errors.0.lock().unwrap().clone()).unwrap(),
+            "{\"line\":2,\"error\":\"key must be a string at column 2\",\"text\":\"{oops\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_float_format() {
+        let input = "{\"a\":1,\"b\":1.0,\"c\":0.128,\"d\":[2.7]}\n";
+
+        for (float_format, format, expected) in [
+            (
+                "shortest",
+                Format::Ndjson,
+                "{\"a\":1,\"b\":1.0,\"c\":0.128,\"d\":[2.7]}\n",
+            ),
+            (
+                "fixed:2",
+                Format::Ndjson,
+                "{\"a\":1,\"b\":1.00,\"c\":0.13,\"d\":[2.70]}\n",
+            ),
+            (
+                "fixed:0",
+                Format::Ndjson,
+                "{\"a\":1,\"b\":1,\"c\":0,\"d\":[3]}\n",
+            ),
+            ("fixed:1", Format::Csv, "a,b,c,d\n1,1.0,0.1,[2.7]\n"),
+        ] {
+            let options = Options {
+                float_format: float_format.parse().unwrap(),
+                format,
+                ..Options::default()
+            };
+
+            let mut output = Vec::new();
+            convert(Cursor::new(input), &mut output, &options).unwrap();
+
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                expected,
+                "{}",
+                float_format
+            );
+        }
+
+        let options = Options {
+            float_format: FloatFormat::Fixed(1),
+            pretty: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new("[0.26]\n"), &mut output, &options).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "[\n[\n  0.3\n]\n]");
+
+        assert!("fixed:".parse::<FloatFormat>().is_err());
+        assert!("round".parse::<FloatFormat>().is_err());
+    }
+
+    #[test]
+    fn test_infer_schema() {
+        let input =
+            "{\"id\":1,\"email\":\"a@example.com\"}\n{\"id\":2}\n{\"id\":3.5,\"email\":null}\n";
+        let options = Options {
+            infer_schema: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(stats.records, 3);
+        assert_eq!(
+            serde_json::from_slice::<Value>(&output).unwrap(),
+            serde_json::json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {
+                    "id": {"type": "number"},
+                    "email": {"type": ["null", "string"]}
+                },
+                "required": ["id"]
+            })
+        );
+    }
+
+    #[test]
+    fn test_compact_scalar_arrays() {
+        let options = Options {
+            pretty: true,
+            compact_scalar_arrays: true,
+            float_format: FloatFormat::Fixed(1),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        let input = "{\"ids\":[1,2,3],\"points\":[[0.5,1],[]],\"tags\":[\"a\",null],\"groups\":[{\"n\":[true]},4]}\n";
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[
+{
+  "ids": [1, 2, 3],
+  "points": [
+    [0.5, 1],
+    []
+  ],
+  "tags": ["a", null],
+  "groups": [
+    {
+      "n": [true]
+    },
+    4
+  ]
+}
+]"#
+        );
+    }
+
+    #[test]
+    fn test_ascii() {
+        let options = Options {
+            ascii: true,
+            wrap: Wrap::Field("café".to_string()),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(
+            Cursor::new("{\"naïve\":\"crème brûlée 🍮!\",\"n\":\"\\u00e9\\n\"}\n"),
+            &mut output,
+            &options,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            r#"{"caf\u00e9":[{"na\u00efve":"cr\u00e8me br\u00fbl\u00e9e \ud83c\udf6e!","n":"\u00e9\n"}]}"#
+        );
+        assert!(output.is_ascii());
+        assert_eq!(
+            serde_json::from_str::<Value>(&output).unwrap()["café"][0]["naïve"],
+            "crème brûlée 🍮!"
+        );
+    }
+
+    #[test]
+    fn test_require_uniform_keys() {
+        let input = "{\"a\":1,\"b\":2}\n{\"b\":3,\"a\":4}\n{\"a\":5,\"c\":6}\n{\"a\":7}\n";
+        let mut options = Options {
+            require_uniform_keys: true,
+            ..Options::default()
+        };
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 3: keys differ from the first record's: missing b; extra c"
+        );
+
+        options.lenient = true;
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(stats.skipped, 2);
+        assert_eq!(output, b"[{\"a\":1,\"b\":2},{\"b\":3,\"a\":4}]");
+    }
+
+    #[test]
+    fn test_stop() {
+        /// Sets `stop` when the second line is read.
+        struct Stopping {
+            lines: Vec<&'static str>,
+            stop: Arc<AtomicBool>,
+        }
+
+        impl Read for Stopping {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.lines.len() == 2 {
+                    self.stop.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+
+                let Some(line) = self.lines.pop() else {
+                    return Ok(0);
+                };
+                buf[..line.len()].copy_from_slice(line.as_bytes());
+                Ok(line.len())
+            }
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let options = Options {
+            stop: Some(stop.clone()),
+            ..Options::default()
+        };
+        let reader = Stopping {
+            lines: vec!["3\n", "2\n", "1\n"],
+            stop,
+        };
+
+        let mut output = Vec::new();
+        let stats = convert_all(
+            [
+                Ok(reader),
+                Ok(Stopping {
+                    lines: vec!["4\n"],
+                    stop: Default::default(),
+                }),
+            ],
+            &mut output,
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(stats.records, 2);
+        assert_eq!(output, b"[1,2]");
+    }
+
+    #[test]
+    fn test_count() {
+        let input: &[u8] = b"{\"a\":1}\nnot json\n\n  \n[1,]\n\"s\"\n\xff\n";
+
+        let counts = count_all([Ok(Cursor::new(input))], &Options::default()).unwrap();
+
+        assert_eq!(
+            counts,
+            Counts {
+                records: 2,
+                invalid: 3,
+                blank: 2,
+                bytes: input.len() as u64,
+            }
+        );
+    }
+
+    #[test]
+    fn test_max_records() {
+        let input = "1\n\n{\"a\":2}\nbad\n3\n";
+        let mut options = Options {
+            max_records: Some(2),
+            lenient: true,
+            ..Options::default()
+        };
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(
+            err,
+            Jl2jsError::TooManyRecords { line: 5, max: 2 }
+        ));
+
+        options.max_records = Some(3);
+        options.passthrough = true;
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"[1,{\"a\":2},3]");
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let input = "1\n{\"a\":[2]}\n{\"a\":[{\"b\":3}]}\n";
+        let mut options = Options {
+            max_depth: Some(2),
+            ..Options::default()
+        };
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::TooDeep { line: 3, max: 2 }));
+
+        options.lenient = true;
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"[1,{\"a\":[2]}]");
+    }
+
+    #[test]
+    fn test_ascii_keys_only() {
+        let input = "{\"name\": \"Zoë\"}\n{\"user\": {\"prénom\": \"a\"}}\n";
+        let mut options = Options {
+            ascii_keys_only: true,
+            ..Options::default()
+        };
+
+        let err = convert(Cursor::new(input), &mut Vec::new(), &options).unwrap_err();
+        assert!(matches!(
+            err,
+            Jl2jsError::NonAsciiKey { line: 2, ref key } if key == "prénom"
+        ));
+
+        options.lenient = true;
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, "[{\"name\":\"Zoë\"}]".as_bytes());
+    }
+
+    #[test]
+    fn test_jsonpath() {
+        let input = concat!(
+            "{\"id\": 1, \"items\": [{\"sku\": \"a\", \"qty\": 2}, {\"sku\": \"b\", \"qty\": 1}]}\n",
+            "{\"id\": 2, \"items\": []}\n",
+            "{\"id\": 3, \"items\": [{\"sku\": \"c\", \"qty\": 5}]}\n",
+        );
+
+        for (query, expected) in [
+            ("$.items[*].sku", r#"["a","b","c"]"#),
+            (
+                "$.items[?@.qty > 1]",
+                r#"[{"sku":"a","qty":2},{"sku":"c","qty":5}]"#,
+            ),
+        ] {
+            let options = Options {
+                jsonpath: Some(query.parse().unwrap()),
+                ..Options::default()
+            };
+
+            let mut output = Vec::new();
+            convert(Cursor::new(input), &mut output, &options).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_omit_final_newline() {
+        let input = "1\n{\"a\":2}\n";
+
+        for (format, omit, expected) in [
+            (Format::Ndjson, false, "1\n{\"a\":2}\n"),
+            (Format::Ndjson, true, "1\n{\"a\":2}"),
+            (Format::JsonSeq, true, "\x1e1\n\x1e{\"a\":2}\n"),
+        ] {
+            let options = Options {
+                format,
+                omit_final_newline: omit,
+                ..Options::default()
+            };
+
+            let mut output = Vec::new();
+            convert(Cursor::new(input), &mut output, &options).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_coerce() {
+        let input =
+            "{\"id\": \"7\", \"ok\": \"false\", \"zip\": \"02134\", \"note\": \"null\"}\n\"42\"\n";
+        let mut options = Options {
+            coerce: Coerce::ALL,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            output,
+            br#"[{"id":7,"ok":false,"zip":"02134","note":null},"42"]"#
+        );
+
+        options.coerce = "numbers".parse().unwrap();
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            output,
+            br#"[{"id":7,"ok":"false","zip":"02134","note":"null"},"42"]"#
+        );
+    }
+
+    #[test]
+    fn test_buffer_to_buffer() {
+        // Only Read and Write are needed, as when built for WebAssembly
+        // without the cli feature.
+        let input: &[u8] = b"{\"a\":1}\n{\"a\":2}\n{\"a\":3}\n";
+        let mut output = Vec::new();
+
+        jsonl_to_json(input, &mut output, false).unwrap();
+        assert_eq!(output, b"[{\"a\":1},{\"a\":2},{\"a\":3}]");
+
+        let options = Options {
+            sample: Some(0.5),
+            ..Options::default()
+        };
+        let result = convert(input, Vec::new(), &options);
+        assert_eq!(result.is_ok(), cfg!(feature = "cli"));
+    }
+
+    #[test]
+    fn test_defer_open() {
+        let options = Options {
+            defer_open: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        let result = convert(Cursor::new("oops\n{\"a\":1}\n"), &mut output, &options);
+        assert!(matches!(result, Err(Jl2jsError::Parse { line: 1, .. })));
+        assert!(output.is_empty());
+
+        let mut output = Vec::new();
+        convert(Cursor::new("\n"), &mut output, &options).unwrap();
+        assert_eq!(output, b"[]");
+
+        let mut output = Vec::new();
+        convert(Cursor::new("{\"a\":1}\n"), &mut output, &options).unwrap();
+        assert_eq!(output, b"[{\"a\":1}]");
+    }
+
+    #[test]
+    fn test_yaml_output() {
+        let input = "{\"name\": \"a\", \"tags\": [\"x\", \"y\"]}\n{\"name\": \"b: c\", \"id\": 2, \"ok\": null}\n";
+        let options = Options {
+            format: Format::Yaml,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "- name: a\n  tags:\n  - x\n  - y\n- name: 'b: c'\n  id: 2\n  ok: null\n"
+        );
+
+        let mut output = Vec::new();
+        convert(Cursor::new(""), &mut output, &options).unwrap();
+        assert_eq!(output, b"[]\n");
+    }
+
+    #[test]
+    fn test_msgpack_output() {
+        let input = "{\"id\": \"a\", \"n\": 1, \"tags\": [\"x\", null]}\n{\"id\": \"b\", \"n\": -2.5, \"ok\": true}\n";
+        let records: Vec<Value> = JsonlReader::new(input.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let mut options = Options {
+            format: Format::Msgpack,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        let decoded: Value = rmp_serde::from_slice(&output).unwrap();
+        assert_eq!(decoded, Value::Array(records.clone()));
+
+        options.key_by = Some(KeyBy {
+            field: "id".to_string(),
+            on_duplicate: OnDuplicate::Error,
+        });
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        let decoded: Value = rmp_serde::from_slice(&output).unwrap();
+        assert_eq!(
+            decoded,
+            serde_json::json!({"a": records[0], "b": records[1]})
+        );
+    }
+
+    #[test]
+    fn test_lines_as_strings() {
+        let input = "plain text\nsay \"hi\"\nC:\\logs\\app.log\n\n{\"a\": 1}\r\n";
+        let options = Options {
+            lines_as_strings: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"["plain text","say \"hi\"","C:\\logs\\app.log","{\"a\": 1}"]"#
+        );
+    }
+
+    #[test]
+    fn test_convert_with() {
+        let input = "{\"id\": 1}\n{\"id\": 2}\n{\"id\": \"x\"}\n{\"id\": 3}\n{\"id\": 4}\n";
+        let transform = |record: &mut Value| {
+            let id = record["id"].as_u64().ok_or("id is not a number")?;
+            record["even"] = (id % 2 == 0).into();
+            Ok::<_, String>(id != 3)
+        };
+        let mut options = Options::default();
+
+        let err =
+            convert_with([Ok(input.as_bytes())], Vec::new(), &options, transform).unwrap_err();
+        assert!(matches!(err, Jl2jsError::Transform { line: 3, .. }));
+        assert_eq!(err.to_string(), "line 3: id is not a number");
+
+        // Records dropped by the transform don't count towards the limit.
+        options.lenient = true;
+        options.skip = 1;
+        options.limit = Some(2);
+
+        let mut output = Vec::new();
+        let stats = convert_with([Ok(input.as_bytes())], &mut output, &options, transform).unwrap();
+        assert_eq!(output, br#"[{"id":2,"even":true},{"id":4,"even":true}]"#);
+        assert_eq!(stats.skipped, 1);
+    }
+
+    #[test]
+    fn test_skip_lines() {
+        let input = "oops\n\n{\"a\":1}\n{\"a\":2}\n{\"a\":3}\nnot json\n";
+        let mut options = Options {
+            skip_lines: 2,
+            limit: Some(2),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, br#"[{"a":1},{"a":2}]"#);
+
+        options.limit = None;
+
+        let err = convert(Cursor::new(input), Vec::new(), &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::Parse { line: 6, .. }));
+    }
+
+    #[test]
+    fn test_tsv_output() {
+        let input = "{\"id\": 1, \"city\": \"Portland, OR\", \"tags\": [\"a\", \"b\"]}\n{\"id\": 2, \"city\": \"Austin, TX\", \"tags\": []}\n";
+        let options = Options {
+            format: Format::Tsv,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "id\tcity\ttags\n\
+             1\tPortland, OR\t[\"a\",\"b\"]\n\
+             2\tAustin, TX\t[]\n"
+        );
+    }
+
+    #[test]
+    fn test_max_output_bytes() {
+        let input: String = (0..10).map(|i| format!("{{\"id\":{}}}\n", i)).collect();
+
+        for (pretty, max) in [(false, 30), (true, 40), (false, 1)] {
+            let options = Options {
+                pretty,
+                max_output_bytes: Some(max),
+                ..Options::default()
+            };
+
+            let mut output = Vec::new();
+            let stats = convert(Cursor::new(&input), &mut output, &options).unwrap();
+            let records: Vec<Value> = serde_json::from_slice(&output).unwrap();
+
+            assert!(output.len() <= max as usize || records.is_empty());
+            assert_eq!(records.len(), stats.records);
+        }
+
+        let options = Options {
+            max_output_bytes: Some(30),
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        convert(Cursor::new(&input), &mut output, &options).unwrap();
+        assert_eq!(output, br#"[{"id":0},{"id":1},{"id":2}]"#);
+    }
+
+    #[test]
+    fn test_canonical() {
+        // The example from RFC 8785, section 3.2.2.
+        let input = r#"{"numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001], "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/", "literals": [null, true, false]}"#;
+        let mut options = Options {
+            canonical: true,
+            pretty: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}]"#
+        );
+
+        // Sorting by UTF-16 puts U+FB33 after U+1F600, unlike UTF-8.
+        let input = r#"{"\u20ac": 1, "\r": 2, "\ufb33": 3, "1": 4, "\ud83d\ude00": 5, "\u0080": 6, "\u00f6": 7}"#;
+        options.format = Format::Ndjson;
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"\\r\":2,\"1\":4,\"\u{80}\":6,\"ö\":7,\"€\":1,\"😀\":5,\"\u{fb33}\":3}\n"
+        );
+    }
+
+    #[test]
+    fn test_canonical_numbers() {
+        let input = "[1e21, 1e20, 1e-7, 0.000001, -0.0, 5e-324, 1.0, -12.5, 9007199254740993]";
+        let options = Options {
+            canonical: true,
+            format: Format::Ndjson,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "[1e+21,100000000000000000000,1e-7,0.000001,0,5e-324,1,-12.5,9007199254740992]\n"
+        );
+    }
+
+    #[test]
+    fn test_wrap_scalars() {
+        let input = "{\"a\": 1}\n2\n[3, 4]\n";
+        let options = Options {
+            wrap_scalars: Some("value".to_string()),
+            add_fields: vec![("env".to_string(), Value::from("prod"))],
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[{"a":1,"env":"prod"},{"value":2,"env":"prod"},{"value":[3,4],"env":"prod"}]"#
+        );
+    }
+
+    #[test]
+    fn test_parallel_errors_in_order() {
+        // The first bad line is reported, however it's bad.
+        let input = "{\"a\":1}\n{oops\n{\"a\": \"far too long\"}\n";
+        let options = Options {
+            jobs: 4,
+            max_line_bytes: Some(16),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        let err = convert(Cursor::new(input), &mut output, &options).unwrap_err();
+        assert!(matches!(err, Jl2jsError::Parse { line: 2, .. }), "{}", err);
+
+        // Lines past the limit aren't read, so can't fail.
+        let options = Options {
+            limit: Some(1),
+            ..options
+        };
+
+        let mut output = Vec::new();
+        convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(output, b"[{\"a\":1}]");
+    }
+
+    #[test]
+    fn test_rename_conflict_not_counted() {
+        let input = "{\"a\":1}\n{\"a\":2,\"b\":0}\n{\"a\":3}\n{\"a\":4}\n";
+        let options = Options {
+            rename: vec![("a".to_string(), "b".to_string())],
+            skip: 1,
+            limit: Some(2),
+            lenient: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(output, b"[{\"b\":3},{\"b\":4}]");
+    }
+
+    #[test]
+    fn test_unwritable_not_counted() {
+        let input = "{\"id\":1}\n{\"x\":2}\n{\"id\":3}\n";
+        let mut options = Options {
+            key_by: Some(KeyBy {
+                field: "id".to_string(),
+                on_duplicate: OnDuplicate::Error,
+            }),
+            limit: Some(2),
+            lenient: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(output, b"{\"1\":{\"id\":1},\"3\":{\"id\":3}}");
+
+        let input = "{\"a\":1}\n{\"b\":2}\n{\"a\":3}\n";
+        options.key_by = None;
+        options.format = Format::Csv;
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(output, b"a\n1\n3\n");
+
+        let input = "{\"a\":1}\n[2]\n{\"b\":3}\n{\"c\":4}\n";
+        options.format = Format::Array;
+        options.merge = Some(MergeArrays::Replace);
+
+        let mut output = Vec::new();
+        let stats = convert(Cursor::new(input), &mut output, &options).unwrap();
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(output, b"{\"a\":1,\"b\":3}");
+    }
+}
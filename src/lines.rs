@@ -0,0 +1,256 @@
+use std::cell::Cell;
+use std::io::{BufRead, ErrorKind, Read};
+use std::str::FromStr;
+
+/// What separates one record from the next in the input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Delimiter {
+    /// `\n`, with a `\r` right before it also removed.
+    #[default]
+    Newline,
+    /// Only `\r\n`. A lone `\n` is kept as part of the record.
+    Crlf,
+    /// Any single byte, such as the record separator (0x1E) that starts each
+    /// text in an RFC 7464 `application/json-seq` stream.
+    Byte(u8),
+}
+
+impl Delimiter {
+    fn terminator(self) -> u8 {
+        match self {
+            Delimiter::Newline | Delimiter::Crlf => b'\n',
+            Delimiter::Byte(byte) => byte,
+        }
+    }
+}
+
+impl FromStr for Delimiter {
+    type Err = String;
+
+    /// Parses `lf`, `crlf`, `rs` (0x1E), a hex byte like `0x1e`, or a single
+    /// ASCII character.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lf" | "\\n" => return Ok(Delimiter::Newline),
+            "crlf" | "\\r\\n" => return Ok(Delimiter::Crlf),
+            "rs" => return Ok(Delimiter::Byte(0x1E)),
+            _ => {}
+        }
+
+        if let Some(hex) = s.strip_prefix("0x") {
+            return u8::from_str_radix(hex, 16)
+                .map(Delimiter::Byte)
+                .map_err(|e| format!("invalid byte {:?}: {}", s, e));
+        }
+
+        match s.as_bytes() {
+            [byte] => Ok(Delimiter::Byte(*byte)),
+            _ => Err(format!(
+                "expected lf, crlf, rs, a hex byte like 0x1e or a single character, got {:?}",
+                s
+            )),
+        }
+    }
+}
+
+/// A single line of input, without its line terminator.
+pub(crate) enum Line {
+    Data(Vec<u8>),
+    /// The line was longer than the configured limit. Its contents were
+    /// discarded while reading rather than buffered.
+    TooLong,
+}
+
+/// Splits input into lines like [`BufRead::split`], but never buffers more
+/// than `max_len` bytes at a time.
+pub(crate) struct LineReader<R> {
+    reader: R,
+    delimiter: Delimiter,
+    max_len: Option<usize>,
+    /// Bytes consumed from `reader` so far.
+    bytes: u64,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub(crate) fn new(reader: R, delimiter: Delimiter, max_len: Option<usize>) -> Self {
+        Self {
+            reader,
+            delimiter,
+            max_len,
+            bytes: 0,
+        }
+    }
+
+    pub(crate) fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+impl<R: BufRead> Iterator for LineReader<R> {
+    type Item = std::io::Result<Line>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = Vec::new();
+        let mut len = 0;
+        let mut too_long = false;
+        let mut read_any = false;
+        let mut last = None;
+        let terminator = self.delimiter.terminator();
+
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if available.is_empty() {
+                break;
+            }
+
+            read_any = true;
+
+            let (mut chunk, used, mut done) = match available.iter().position(|&b| b == terminator)
+            {
+                Some(i) => (&available[..i], i + 1, true),
+                None => (available, available.len(), false),
+            };
+
+            last = chunk.last().copied().or(last);
+
+            // Only a `\n` preceded by `\r` ends a CRLF-delimited line.
+            if done && self.delimiter == Delimiter::Crlf && last != Some(b'\r') {
+                chunk = &available[..used];
+                last = Some(b'\n');
+                done = false;
+            }
+
+            len += chunk.len();
+
+            if self.max_len.is_some_and(|max| len > max) {
+                too_long = true;
+                line = Vec::new();
+            } else {
+                line.extend_from_slice(chunk);
+            }
+
+            self.reader.consume(used);
+            self.bytes += used as u64;
+
+            if done {
+                break;
+            }
+        }
+
+        if !read_any {
+            return None;
+        }
+
+        if too_long {
+            return Some(Ok(Line::TooLong));
+        }
+
+        let crlf = matches!(self.delimiter, Delimiter::Newline | Delimiter::Crlf);
+
+        if crlf && line.last() == Some(&b'\r') {
+            line.pop();
+        }
+
+        Some(Ok(Line::Data(line)))
+    }
+}
+
+/// Counts the newlines read through it, so the line a reader is on can be
+/// told while something else reads from it.
+pub(crate) struct NewlineCounter<'a, R> {
+    reader: R,
+    newlines: &'a Cell<usize>,
+}
+
+impl<'a, R> NewlineCounter<'a, R> {
+    pub(crate) fn new(reader: R, newlines: &'a Cell<usize>) -> Self {
+        Self { reader, newlines }
+    }
+}
+
+impl<R: Read> Read for NewlineCounter<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        let newlines = buf[..read].iter().filter(|&&b| b == b'\n').count();
+        self.newlines.set(self.newlines.get() + newlines);
+
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+
+    fn collect(input: &str, delimiter: Delimiter, max_len: Option<usize>) -> Vec<Option<String>> {
+        // A tiny buffer makes sure lines spanning several reads are handled.
+        let reader = BufReader::with_capacity(4, Cursor::new(input.to_string()));
+
+        LineReader::new(reader, delimiter, max_len)
+            .map(|line| match line.unwrap() {
+                Line::Data(data) => Some(String::from_utf8(data).unwrap()),
+                Line::TooLong => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_splits_lines() {
+        assert_eq!(
+            collect("first line\r\nsecond\n\nlast", Delimiter::Newline, None),
+            vec![
+                Some("first line".to_string()),
+                Some("second".to_string()),
+                Some(String::new()),
+                Some("last".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_len() {
+        assert_eq!(
+            collect(
+                "short\nthis line is too long\nok\n",
+                Delimiter::Newline,
+                Some(8)
+            ),
+            vec![Some("short".to_string()), None, Some("ok".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_crlf() {
+        assert_eq!(
+            collect("first\r\nsplit\nrecord\r\n\r\nlast", Delimiter::Crlf, None),
+            vec![
+                Some("first".to_string()),
+                Some("split\nrecord".to_string()),
+                Some(String::new()),
+                Some("last".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_separator() {
+        assert_eq!(
+            collect(
+                "\x1e{\"a\":1}\n\x1e{\"a\":2}\r\n",
+                "rs".parse().unwrap(),
+                None
+            ),
+            vec![
+                Some(String::new()),
+                Some("{\"a\":1}\n".to_string()),
+                Some("{\"a\":2}\r\n".to_string()),
+            ]
+        );
+    }
+}
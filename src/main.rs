@@ -1,187 +1,1420 @@
+mod config;
+mod progress;
+mod watch;
+
 use clap::Parser;
-use serde_json::Value;
-use std::io::{stdin, stdout, BufRead, BufReader, BufWriter, Stdin, Stdout, Write};
-use std::path::PathBuf;
-use std::{fs::File, io::Read};
+use glob::Pattern;
+use jl2js::{
+    check_all, convert_all, convert_chunks, count_all, json_to_jsonl, Checksum, Coerce,
+    Compression, Dedup, Delimiter, ErrorLog, ExistingArray, Filter, FloatFormat, Format, GroupBy,
+    InputFormat, InputSource, Jl2jsError, JsonPath, KeyBy, MergeArrays, OnDuplicate, Options,
+    OutputSink, Wrap,
+};
+use progress::{Progress, ProgressReader};
+use std::cell::RefCell;
+use std::fs;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Exit statuses, documented in `--help`.
+const EXIT_INVALID: u8 = 1;
+const EXIT_IO: u8 = 2;
+const EXIT_USAGE: u8 = 3;
+const EXIT_INTERRUPTED: u8 = 130;
 
-enum InputSource {
-    File(File),
-    Stdin(Stdin),
+#[derive(Debug, Parser)]
+#[clap(after_help = "Exit status:\n  \
+    0  success\n  \
+    1  invalid input, such as a line that isn't JSON or breaks the schema (with --check, any invalid line)\n  \
+    2  an error reading or writing files (with --continue-on-io-error, once the readable inputs are converted)\n  \
+    3  invalid arguments\n  \
+    130  interrupted with Ctrl-C\n\n\
+The first Ctrl-C stops reading and finishes the output, closing the array, so what was written \
+so far is still valid JSON; if jl2js is waiting for input, that happens once the next line arrives. \
+A second Ctrl-C stops right away. With --follow and --watch, Ctrl-C stops right away.\n\n\
+Defaults for any option can be set in .jl2js.toml in the current directory, or the file given \
+with --config, keyed by the option's long name, like indent = 4 or lenient = true. Options given \
+on the command line take precedence over the file, which takes precedence over built-in defaults.")]
+struct Cli {
+    #[clap(
+        long,
+        help = "Input file (JSONL), or a directory whose *.jsonl files are read in name order. Repeat to concatenate several inputs"
+    )]
+    input: Vec<PathBuf>,
+    #[clap(
+        long,
+        requires = "input",
+        help = "Only read files in the input directory whose names match this pattern"
+    )]
+    glob: Option<Pattern>,
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Read default options from this TOML file instead of .jl2js.toml in the current directory"
+    )]
+    config: Option<PathBuf>,
+    #[clap(long, help = "Output file (JSON)")]
+    output: Option<PathBuf>,
+    #[clap(
+        long,
+        overrides_with = "force",
+        conflicts_with_all = ["append", "watch"],
+        help = "Refuse to overwrite an existing output file"
+    )]
+    no_clobber: bool,
+    #[clap(
+        long,
+        overrides_with = "no_clobber",
+        help = "Overwrite an existing output file, undoing an earlier --no-clobber (the default)"
+    )]
+    force: bool,
+    #[clap(
+        long,
+        requires = "output",
+        conflicts_with_all = ["format", "ndjson", "output_seq", "key_by", "gzip_out", "reverse"],
+        help = "Append records to the JSON array in the output file (the file is replaced once the new array is complete)"
+    )]
+    append: bool,
+    #[clap(long, help = "Pretty print output")]
+    pretty: bool,
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Pretty print only objects and arrays nested at most N deep, counting each record as 1, and the rest compactly (implies --pretty)"
+    )]
+    pretty_depth: Option<usize>,
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["pretty", "pretty_depth", "compact_scalar_arrays", "compact", "passthrough", "follow", "watch", "check", "count_only", "reverse"],
+        help = "Pretty print output only if it has fewer than N records. The inputs are read twice, first to count the records, so with stdin or a pipe the output is left compact, with a warning",
+    )]
+    pretty_if_under: Option<usize>,
+    #[clap(
+        long,
+        help = "Keep arrays holding only scalars on one line, like [1, 2, 3], when pretty printing (implies --pretty)"
+    )]
+    compact_scalar_arrays: bool,
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Indent pretty output with N spaces (implies --pretty)"
+    )]
+    indent: Option<usize>,
+    #[clap(
+        long,
+        conflicts_with = "indent",
+        help = "Indent pretty output with tabs (implies --pretty)"
+    )]
+    indent_tabs: bool,
+    #[clap(
+        long,
+        value_enum,
+        conflicts_with_all = ["ndjson", "output_seq"],
+        help = "Output format [default: array]"
+    )]
+    format: Option<Format>,
+    #[clap(
+        long,
+        help = "Pick the output format from the --output file extension, unless a format is given",
+        long_help = "Pick the output format from the --output file extension, ignoring case and any .gz:\n  \
+            .json             array\n  \
+            .jsonl, .ndjson   NDJSON\n  \
+            .csv              CSV\n  \
+            .tsv              TSV\n  \
+            .yaml, .yml       YAML\n  \
+            .msgpack          MessagePack\n\
+            Other extensions keep the default. --format, --ndjson and --output-seq always take precedence."
+    )]
+    out_ext_infer: bool,
+    #[clap(
+        long,
+        default_value = "array",
+        value_name = "none|array|field:NAME",
+        conflicts_with_all = ["append"],
+        help = "What encloses the output's records: nothing, an array, or an array in the NAME field of an object"
+    )]
+    wrap: Wrap,
+    #[clap(
+        long,
+        help = "Write the opening [ only once the first record is ready (or [] for none), so failing before then writes nothing"
+    )]
+    defer_open: bool,
+    #[clap(
+        long,
+        default_value = "shortest",
+        value_name = "shortest|fixed:N",
+        help = "How floats are written: with the fewest digits that read back the same, or exactly N after the decimal point (integers like 1 are always written as they are)"
+    )]
+    float_format: FloatFormat,
+    #[clap(
+        long,
+        conflicts_with = "reverse",
+        help = "Escape every non-ASCII character in strings as \\uXXXX so the output is 7-bit clean (CSV and TSV cells are written as they are)"
+    )]
+    ascii: bool,
+    #[clap(
+        long,
+        help = "With --format csv or tsv, add keys first seen in later records to the header (buffers all rows)"
+    )]
+    extend_header: bool,
+    #[clap(long, help = "Write one record per line instead of a JSON array")]
+    ndjson: bool,
+    #[clap(
+        long,
+        conflicts_with = "ndjson",
+        help = "Write an RFC 7464 application/json-seq stream: each record is framed as 0x1E <record> 0x0A"
+    )]
+    output_seq: bool,
+    #[clap(
+        long,
+        value_name = "yes|no",
+        default_value = "yes",
+        action = clap::ArgAction::Set,
+        value_parser = clap::builder::BoolishValueParser::new(),
+        help = "Whether the last record of --ndjson or --reverse output ends with a newline; --output-seq records always do"
+    )]
+    final_newline: bool,
+    #[clap(
+        long,
+        value_name = "FIELD",
+        conflicts_with_all = ["ndjson", "output_seq"],
+        help = "Write an object keyed by each record's FIELD instead of an array"
+    )]
+    key_by: Option<String>,
+    #[clap(
+        long,
+        conflicts_with_all = ["key_by", "ndjson", "output_seq", "append", "split", "follow"],
+        help = "Deep-merge every record into a single object instead of writing an array"
+    )]
+    merge: bool,
+    #[clap(
+        long,
+        value_name = "FIELD",
+        conflicts_with_all = ["key_by", "merge", "ndjson", "output_seq", "append", "split", "follow", "infer_schema"],
+        help = "Write an object of arrays instead, grouping records by their FIELD (all records are held in memory until the input ends)"
+    )]
+    group_by: Option<String>,
+    #[clap(
+        long,
+        value_name = "NAME",
+        requires = "group_by",
+        help = "With --group-by, put records without FIELD in the NAME group instead of dropping them"
+    )]
+    group_missing: Option<String>,
+    #[clap(
+        long,
+        value_enum,
+        requires = "merge",
+        default_value_t = MergeArrays::Replace,
+        help = "With --merge, whether an array replaces an earlier one or is appended to it"
+    )]
+    merge_arrays: MergeArrays,
+    #[clap(
+        long,
+        value_enum,
+        requires = "key_by",
+        default_value_t = OnDuplicate::Error,
+        help = "What to do when two records have the same key"
+    )]
+    on_duplicate: OnDuplicate,
+    #[clap(
+        long,
+        help = "Write each element of a line holding an array as a separate record"
+    )]
+    unwrap_arrays: bool,
+    #[clap(
+        long = "where",
+        value_name = "KEY=VALUE",
+        help = "Only keep objects whose top-level KEY is the string VALUE"
+    )]
+    filter: Option<Filter>,
+    #[clap(
+        long,
+        value_name = "KEY=VALUE",
+        value_parser = parse_field,
+        help = "Add KEY to every object record. VALUE is used as JSON if it parses as JSON, and as a string otherwise. Repeatable"
+    )]
+    add_field: Vec<(String, serde_json::Value)>,
+    #[clap(
+        long,
+        value_name = "KEY",
+        help = "Add KEY to every object record, set to the time it was converted as an RFC 3339 UTC timestamp"
+    )]
+    add_timestamp: Option<String>,
+    #[clap(
+        long,
+        value_name = "KEY",
+        help = "Add the input line number of each record under KEY, counting blank lines; records that aren't objects become {KEY: N, \"value\": record}"
+    )]
+    with_line_number: Option<String>,
+    #[clap(
+        long,
+        help = "Keep a record's own value for a key instead of replacing it with --add-field or --add-timestamp"
+    )]
+    keep_existing_fields: bool,
+    #[clap(
+        long,
+        help = "Remove keys with null values from every object, including nested ones (null array elements are kept)"
+    )]
+    drop_nulls: bool,
+    #[clap(
+        long,
+        help = "Replace each \\r\\n inside string values with \\n, in every record (keys are left alone)"
+    )]
+    normalize_newlines: bool,
+    #[clap(
+        long,
+        help = "Sort object keys recursively instead of keeping their input order"
+    )]
+    sort_keys: bool,
+    #[clap(
+        long,
+        value_name = "P",
+        value_parser = parse_probability,
+        help = "Keep each record with probability P, from 0.0 to 1.0 (an approximate fraction, not an exact count)"
+    )]
+    sample: Option<f64>,
+    #[clap(
+        long,
+        requires = "sample",
+        help = "Seed --sample's random choices so runs are reproducible"
+    )]
+    seed: Option<u64>,
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Pass over the first N records (counted after --where)"
+    )]
+    skip: usize,
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        conflicts_with = "whitespace_separated",
+        help = "Pass over the first N lines of each input, counting blank, comment and invalid lines too, e.g. to resume a failed run (unlike --skip, which counts records)"
+    )]
+    skip_lines: usize,
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Stop after writing N records (counted after --where and --skip)"
+    )]
+    limit: Option<usize>,
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        help = "Parse lines on N threads (output order is preserved)"
+    )]
+    jobs: usize,
+    #[clap(
+        long,
+        help = "Write only the first of identical records, ignoring key order (remembers every record written, see --dedup-by)"
+    )]
+    dedup: bool,
+    #[clap(
+        long,
+        value_name = "FIELD",
+        conflicts_with = "dedup",
+        help = "Write only the first record with each value of FIELD, remembering only those values"
+    )]
+    dedup_by: Option<String>,
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Write only the value at this dotted path of each record, like user.address.city or items.0.id, dropping records without one"
+    )]
+    extract: Option<String>,
+    #[clap(
+        long,
+        value_name = "QUERY",
+        conflicts_with = "extract",
+        help = "Write every value this JSONPath query (RFC 9535) selects from each record as a record of its own, like $.items[*] or $..book[?@.price < 10]"
+    )]
+    jsonpath: Option<JsonPath>,
+    #[clap(
+        long,
+        value_name = "KEYS",
+        value_delimiter = ',',
+        help = "Keep only these comma-separated top-level keys of each object"
+    )]
+    select: Option<Vec<String>>,
+    #[clap(
+        long,
+        value_name = "OLD=NEW",
+        value_parser = parse_rename,
+        help = "Rename key OLD of each object to NEW, before the other transforms. OLD may be a dotted path like user.name; NEW is its new name in the same object. Repeatable"
+    )]
+    rename: Vec<(String, String)>,
+    #[clap(
+        long,
+        requires = "rename",
+        help = "With --rename, replace a key already named NEW instead of failing"
+    )]
+    rename_overwrite: bool,
+    #[clap(
+        long,
+        value_name = "KINDS",
+        num_args = 0..=1,
+        default_missing_value = "numbers,booleans,null",
+        help = "Turn strings in each object that spell a number, boolean or null exactly, like \"42\" or \"true\" but not \"007\", into those values. Give comma-separated KINDS (numbers, booleans, null) to coerce only some"
+    )]
+    coerce: Option<Coerce>,
+    #[clap(
+        long,
+        value_name = "KEYS",
+        value_delimiter = ',',
+        help = "Remove these comma-separated keys from each object (dotted paths reach into nested objects; array indices are not supported)"
+    )]
+    drop: Vec<String>,
+    #[clap(
+        long,
+        help = "Flatten nested objects and arrays into dotted keys like a.b and a.0"
+    )]
+    flatten: bool,
+    #[clap(
+        long,
+        value_name = "SEP",
+        default_value = ".",
+        requires = "flatten",
+        help = "Separator used to join flattened keys"
+    )]
+    flatten_sep: String,
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "pretty", "pretty_depth", "compact_scalar_arrays", "compact", "canonical", "sort_keys", "key_by", "group_by", "merge", "unwrap_arrays", "filter", "select",
+            "extract", "jsonpath", "dedup", "dedup_by", "rename", "coerce", "drop", "flatten", "drop_nulls", "normalize_newlines", "add_field", "add_timestamp", "with_line_number", "ensure_object", "wrap_scalars", "schema", "require_uniform_keys", "lenient_trailing",
+            "max_depth", "ascii_keys_only", "whitespace_separated", "lines_as_strings", "float_format", "ascii", "infer_schema",
+        ],
+        help = "Only check that each line is valid JSON and copy it to the output unchanged, which is much faster"
+    )]
+    passthrough: bool,
+    #[clap(
+        long,
+        help = "Fail on any record that isn't an object, or skip it with --lenient"
+    )]
+    ensure_object: bool,
+    #[clap(
+        long,
+        value_name = "KEY",
+        conflicts_with = "ensure_object",
+        help = "Wrap each record that isn't an object as {KEY: record}, before the other transforms"
+    )]
+    wrap_scalars: Option<String>,
+    #[clap(
+        long,
+        help = "Drop records that aren't objects instead of passing them through --select/--drop/--flatten"
+    )]
+    skip_non_objects: bool,
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Validate each record against this JSON Schema (nonconforming records are skipped with --lenient)"
+    )]
+    schema: Option<PathBuf>,
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Skip lines that would be an error, like --lenient, writing each to FILE as JSONL with its line number and error"
+    )]
+    collect_errors: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "Skip malformed lines, including ones that aren't valid UTF-8, and records that fail a check such as --schema or --key-by, instead of aborting"
+    )]
+    lenient: bool,
+    #[clap(
+        long,
+        help = "Fail on any record whose top-level keys differ from the first record's, naming the keys, or skip it with --lenient"
+    )]
+    require_uniform_keys: bool,
+    #[clap(
+        long,
+        conflicts_with = "whitespace_separated",
+        help = "Report each object key that appears more than once in the same object to stderr, with its line (only the last value is kept in the output)"
+    )]
+    warn_duplicate_keys: bool,
+    #[clap(
+        long,
+        help = "Ignore anything after the first JSON value on a line, such as free text following a JSON prefix"
+    )]
+    lenient_trailing: bool,
+    #[cfg(feature = "relaxed")]
+    #[clap(
+        long,
+        help = "Accept JSON5 input (comments, trailing commas, single quotes)"
+    )]
+    relaxed: bool,
+    #[clap(
+        long,
+        help = "Strip leading and trailing whitespace, including Unicode spaces, from each line before parsing it"
+    )]
+    trim: bool,
+    #[clap(long, help = "Skip lines starting with # or // as comments")]
+    allow_comments: bool,
+    #[clap(
+        long,
+        value_enum,
+        conflicts_with_all = ["whitespace_separated", "passthrough", "count_only", "reverse"],
+        help = "Input format [default: jsonl]. CSV needs a header row, whose names become the keys of each row's object"
+    )]
+    input_format: Option<InputFormat>,
+    #[clap(
+        long,
+        requires = "input_format",
+        help = "With --input-format csv, read cells like 12 or true as numbers and booleans instead of strings"
+    )]
+    csv_infer_types: bool,
+    #[clap(
+        long,
+        conflicts_with_all = ["delimiter", "max_line_bytes", "lenient_trailing", "follow", "trim", "allow_comments"],
+        help = "Read JSON values separated by any whitespace, such as pretty printed objects back to back, instead of one per line"
+    )]
+    whitespace_separated: bool,
+    #[clap(
+        long,
+        conflicts_with_all = ["whitespace_separated", "input_format", "check", "count_only", "warn_duplicate_keys", "lenient_trailing"],
+        help = "Write each line as a JSON string of its text instead of parsing it, turning plain text into an array of strings"
+    )]
+    lines_as_strings: bool,
+    #[clap(
+        long,
+        default_value = "lf",
+        help = "Record delimiter: lf, crlf, rs (0x1E, for RFC 7464 json-seq), a hex byte like 0x1e, or a single character"
+    )]
+    delimiter: Delimiter,
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Reject lines longer than N bytes (skipped with --lenient)"
+    )]
+    max_line_bytes: Option<usize>,
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Fail if the input has more than N records, even with --lenient"
+    )]
+    max_records: Option<usize>,
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with = "split",
+        help = "Stop at the first record that would take the output past N bytes, before compression, and close it there so it stays valid"
+    )]
+    max_output_bytes: Option<u64>,
+    #[clap(
+        long,
+        value_name = "D",
+        help = "Reject records nested more than D arrays or objects deep (skipped with --lenient)"
+    )]
+    max_depth: Option<usize>,
+    #[clap(
+        long,
+        help = "Reject records with a non-ASCII object key at any depth (skipped with --lenient); unlike --ascii, values aren't affected"
+    )]
+    ascii_keys_only: bool,
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["append", "split", "check", "reverse", "follow"],
+        help = "Once done, write the number of records, the number of bytes and the SHA-256 of the output to FILE as JSON"
+    )]
+    manifest: Option<PathBuf>,
+    #[clap(
+        long,
+        conflicts_with_all = ["follow", "reverse"],
+        help = "Skip inputs that can't be opened or fail partway through, keeping the lines read from them, and report them at the end (exit status 2)"
+    )]
+    continue_on_io_error: bool,
+    #[clap(
+        long,
+        help = "Flush output after every input line so a consumer sees each record right away, at some cost in throughput (implied when an input is a named pipe)"
+    )]
+    flush: bool,
+    #[clap(
+        long,
+        conflicts_with_all = ["output", "append", "reverse", "lenient"],
+        help = "Only report whether every line is valid, writing nothing (exit status 1 if any isn't, 2 on I/O errors)"
+    )]
+    check: bool,
+    #[clap(
+        long,
+        conflicts_with_all = ["output", "append", "reverse", "check", "split", "watch", "follow", "manifest", "whitespace_separated"],
+        help = "Only count valid, invalid and blank lines and bytes read, printing them to stdout as JSON instead of converting"
+    )]
+    count_only: bool,
+    #[clap(
+        long,
+        requires = "input",
+        conflicts_with_all = ["format", "output_seq", "key_by", "split", "append", "check", "reverse", "gzip_in"],
+        help = "Keep reading the input file as it grows, like tail -f, writing each record as NDJSON as soon as it's read. Stop with Ctrl-C",
+        long_help = "Keep reading the input file as it grows, like tail -f, writing each record as NDJSON as soon as it's read (implies --ndjson).\n\
+            The file is checked for new lines a few times a second. If it's truncated, or replaced as when logs are rotated, \
+            reading starts over from the beginning of the file now at that path. This never stops by itself: Ctrl-C is the normal way to stop."
+    )]
+    follow: bool,
+    #[clap(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["input", "output", "append", "split", "check", "reverse", "follow", "manifest"],
+        help = "Watch DIR and convert each .jsonl file that appears in it to a .json file beside it, once it's been written. Runs until stopped with Ctrl-C",
+        long_help = "Watch DIR and convert each .jsonl file (or each file matching --glob) that appears in it to a .json file beside it, \
+            with the other options given. Files already in DIR are left alone.\n\
+            A file is converted once the program writing it closes it, or after it's gone a second without changing on platforms \
+            that don't report that; files moved into DIR are converted right away. Files that fail to convert are reported on \
+            stderr and watching carries on. This never stops by itself: Ctrl-C is the normal way to stop."
+    )]
+    watch: Option<PathBuf>,
+    #[clap(
+        long,
+        conflicts_with_all = ["format", "ndjson", "output_seq", "key_by", "merge", "append", "split", "check", "reverse", "follow", "watch"],
+        help = "Instead of converting, write a JSON Schema describing the types seen in every record; fields in every record are required"
+    )]
+    infer_schema: bool,
+    #[clap(long, help = "Convert a JSON array back into JSONL")]
+    reverse: bool,
+    #[clap(
+        long,
+        conflicts_with_all = ["pretty", "pretty_depth", "compact_scalar_arrays", "indent", "indent_tabs"],
+        help = "Emit a deterministic minimal encoding of each record",
+        long_help = "Emit a deterministic minimal encoding of each record, suitable for hashing.\n\
+            Object keys are sorted recursively by their UTF-8 bytes and strings are re-escaped \
+            minimally (only quotes, backslashes and control characters; \\uXXXX escapes are \
+            decoded). Numbers are kept as serde_json formats them (1.0 stays 1.0), Unicode is not \
+            normalized, and duplicate keys keep their last value."
+    )]
+    compact: bool,
+    #[clap(
+        long,
+        conflicts_with_all = ["pretty", "pretty_depth", "compact_scalar_arrays", "indent", "indent_tabs", "float_format", "ascii"],
+        help = "Write each record as RFC 8785 canonical JSON (JCS) for signing and hashing",
+        long_help = "Write each record as RFC 8785 canonical JSON (JCS) for signing and hashing: no whitespace, \
+            keys sorted by their UTF-16 code units and strings escaped minimally, like --compact. Numbers are \
+            written as JavaScript writes a double: integers without a fraction (1.0 is 1), otherwise the \
+            fewest digits that read back the same, with an exponent only from 1e+21 and below 1e-6 (1e-7). \
+            Integers beyond 2^53 are rounded to the nearest double, as JCS requires."
+    )]
+    canonical: bool,
+    #[clap(
+        long,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        requires = "output",
+        conflicts_with_all = ["append", "key_by", "check", "reverse"],
+        help = "Write at most N records to each output file, naming them by numbering --output from 0 (its {} is replaced with the number, or {:03} for one zero padded to 3 digits)"
+    )]
+    split: Option<usize>,
+    #[clap(
+        long,
+        value_name = "BYTES",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Capacity of the input buffer [default: 65536]"
+    )]
+    read_buffer: Option<usize>,
+    #[clap(
+        long,
+        value_name = "BYTES",
+        help = "Capacity of the output buffer [default: 8192]"
+    )]
+    buffer_size: Option<usize>,
+    #[clap(
+        long,
+        help = "Report lines and bytes read to stderr every second, with a percentage when the input size is known"
+    )]
+    progress: bool,
+    #[clap(
+        short,
+        long,
+        action = clap::ArgAction::Count,
+        help = "Log files read and how long they took to stderr; -vv also logs how long each line took to parse"
+    )]
+    verbose: u8,
+    #[clap(long, help = "Print a summary of records and bytes written to stderr")]
+    stats: bool,
+    #[clap(long, help = "Decompress input as gzip (implied by a .gz input file)")]
+    gzip_in: bool,
+    #[clap(long, help = "Compress output as gzip (implied by a .gz output file)")]
+    gzip_out: bool,
+    #[clap(
+        long,
+        help = "Compress gzip output on a thread of its own, overlapping compression with conversion. The output is byte-for-byte the same. Only gzip output is compressed this way; jl2js doesn't write zstd"
+    )]
+    background_compression: bool,
 }
 
-impl Read for InputSource {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        match self {
-            InputSource::File(file) => file.read(buf),
-            InputSource::Stdin(stdin) => stdin.read(buf),
+fn parse_field(s: &str) -> Result<(String, serde_json::Value), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got {:?}", s))?;
+    let value = serde_json::from_str(value).unwrap_or_else(|_| value.into());
+
+    Ok((key.to_string(), value))
+}
+
+fn parse_rename(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+            Ok((from.to_string(), to.to_string()))
         }
+        _ => Err(format!("expected OLD=NEW, got {:?}", s)),
     }
 }
 
-impl InputSource {
-    fn from_file(path: PathBuf) -> std::io::Result<Self> {
-        Ok(Self::File(File::open(path)?))
+fn parse_probability(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+        Ok(_) => Err("must be between 0.0 and 1.0".to_string()),
+        Err(e) => Err(e.to_string()),
     }
+}
 
-    fn from_stdin() -> Self {
-        Self::Stdin(stdin())
-    }
+fn is_gzip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
 }
 
-enum OutputSink {
-    File(File),
-    Stdout(Stdout),
+/// The output format implied by the extension of `path`, for
+/// `--out-ext-infer`.
+fn format_from_path(path: &Path) -> Option<Format> {
+    let path = match path.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("gz") => path.file_stem().map(Path::new)?,
+        _ => path,
+    };
+
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+
+    match ext.as_str() {
+        "json" => Some(Format::Array),
+        "jsonl" | "ndjson" => Some(Format::Ndjson),
+        "csv" => Some(Format::Csv),
+        "tsv" => Some(Format::Tsv),
+        "yaml" | "yml" => Some(Format::Yaml),
+        "msgpack" => Some(Format::Msgpack),
+        _ => None,
+    }
 }
 
-impl OutputSink {
-    fn from_file(path: PathBuf) -> std::io::Result<Self> {
-        Ok(Self::File(File::create(path)?))
+/// Whether `path` is a named pipe, which is read as lines are written to it.
+fn is_fifo(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
     }
 
-    fn from_stdout() -> Self {
-        Self::Stdout(stdout())
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
     }
 }
 
-impl Write for OutputSink {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        match self {
-            OutputSink::File(file) => file.write(buf),
-            OutputSink::Stdout(sink) => sink.write(buf),
+fn with_path(e: std::io::Error, path: &Path) -> std::io::Error {
+    std::io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+/// Whether an `--input` is a URL rather than a path.
+fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+fn open_input(path: Option<PathBuf>, gzip_in: bool) -> std::io::Result<InputSource> {
+    let compression = match &path {
+        _ if gzip_in => Some(Compression::Gzip),
+        Some(path) => Compression::from_path(path),
+        None => None,
+    };
+
+    let source = match &path {
+        #[cfg(feature = "http")]
+        Some(url) if is_url(url) => {
+            let url = url.to_string_lossy();
+            log::info!("reading {}", url);
+            InputSource::from_url(&url)?
         }
+        Some(path) => {
+            log::info!("reading {}", path.display());
+            InputSource::from_file(path.clone()).map_err(|e| with_path(e, path))?
+        }
+        None => {
+            log::info!("reading stdin");
+            InputSource::from_stdin()
+        }
+    };
+
+    match (compression, &path) {
+        (None, _) => Ok(source),
+        (Some(compression), Some(path)) => source
+            .decompress(compression)
+            .map_err(|e| with_path(e, path)),
+        (Some(compression), None) => source.decompress(compression),
     }
+}
+
+fn jsonl_files(dir: &Path, pattern: Option<&Pattern>) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
 
-    fn flush(&mut self) -> std::io::Result<()> {
-        match self {
-            OutputSink::File(file) => file.flush(),
-            OutputSink::Stdout(sink) => sink.flush(),
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if matches_input(&path, pattern) && path.is_file() {
+            files.push(path);
         }
     }
+
+    files.sort();
+
+    Ok(files)
 }
 
-#[derive(Debug, Parser)]
-struct Cli {
-    #[clap(long, help = "Input file (JSONL)")]
-    input: Option<PathBuf>,
-    #[clap(long, help = "Output file (JSON)")]
-    output: Option<PathBuf>,
-    #[clap(long, help = "Pretty print output")]
-    pretty: bool,
+/// Whether the file at `path` in a directory of inputs is read: if its name
+/// matches `pattern`, or without one, if it ends in `.jsonl`.
+fn matches_input(path: &Path, pattern: Option<&Pattern>) -> bool {
+    match (pattern, path.file_name().and_then(|name| name.to_str())) {
+        (Some(pattern), Some(name)) => pattern.matches(name),
+        (Some(_), None) => false,
+        (None, _) => path.extension().is_some_and(|ext| ext == "jsonl"),
+    }
 }
 
-fn process<R: Read, W: Write>(reader: R, writer: W, pretty: bool) -> std::io::Result<()> {
-    let reader = BufReader::new(reader);
-    let mut writer = BufWriter::new(writer);
+fn open_output(
+    path: Option<PathBuf>,
+    append: bool,
+    no_clobber: bool,
+    gzip: bool,
+    background_compression: bool,
+    checksum: Option<&Checksum>,
+) -> std::io::Result<(OutputSink, Option<ExistingArray>)> {
+    let (mut sink, existing) = match path {
+        Some(path) if append => OutputSink::append(path)?,
+        Some(path) if no_clobber => (
+            OutputSink::from_new_file(path.clone()).map_err(|e| clobber_error(e, &path))?,
+            None,
+        ),
+        Some(path) => (OutputSink::from_file(path)?, None),
+        None => (OutputSink::from_stdout(), None),
+    };
+
+    if let Some(checksum) = checksum {
+        sink = sink.checksummed(checksum.clone());
+    }
+
+    let sink = match gzip {
+        true if background_compression => sink.gzip().in_background(),
+        true => sink.gzip(),
+        false => sink,
+    };
 
-    writer.write_all(b"[")?;
+    Ok((sink, existing))
+}
 
-    if pretty {
-        writer.write_all(b"\n")?;
+/// Creates the `--split` chunk at `path`, failing if it exists when
+/// `no_clobber` is set.
+fn create_chunk(path: PathBuf, no_clobber: bool) -> std::io::Result<fs::File> {
+    match no_clobber {
+        true => fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| clobber_error(e, &path)),
+        false => fs::File::create(path),
     }
+}
 
-    let mut first = true;
+/// Says which file `--no-clobber` refused to overwrite.
+fn clobber_error(e: std::io::Error, path: &Path) -> std::io::Error {
+    match e.kind() {
+        ErrorKind::AlreadyExists => std::io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!(
+                "{}: already exists, not overwriting it (--no-clobber); pass --force to overwrite",
+                path.display()
+            ),
+        ),
+        _ => with_path(e, path),
+    }
+}
 
-    for line in reader.lines().flatten() {
-        if !first {
-            writer.write_all(b",")?;
+/// A `--split` output path, with a placeholder for the number of each chunk.
+struct ChunkTemplate {
+    prefix: String,
+    /// Chunk numbers are zero padded to this many digits.
+    width: usize,
+    suffix: String,
+}
 
-            if pretty {
-                writer.write_all(b"\n")?;
-            }
-        }
+impl ChunkTemplate {
+    /// Parses a path containing a single `{}`, or `{:0N}` to zero pad the
+    /// number to N digits.
+    fn parse(path: &Path) -> Result<Self, Jl2jsError> {
+        let invalid = |message: &str| Jl2jsError::Usage(format!("{}: {}", path.display(), message));
+
+        let template = path
+            .to_str()
+            .ok_or_else(|| invalid("--split output path isn't valid UTF-8"))?;
 
-        first = false;
+        let Some((prefix, rest)) = template.split_once('{') else {
+            return Err(invalid("--split output path needs a {} placeholder"));
+        };
 
-        let value: Value = serde_json::from_str(&line)?;
+        let (spec, suffix) = rest
+            .split_once('}')
+            .ok_or_else(|| invalid("unclosed { in --split output path"))?;
 
-        let serialized = if pretty {
-            serde_json::to_string_pretty(&value)?
-        } else {
-            serde_json::to_string(&value)?
+        if suffix.contains(['{', '}']) {
+            return Err(invalid("--split output path can only have one placeholder"));
+        }
+
+        let width = match spec {
+            "" => 0,
+            spec => spec
+                .strip_prefix(":0")
+                .and_then(|width| width.parse().ok())
+                .ok_or_else(|| invalid("expected {} or {:0N} in --split output path"))?,
         };
 
-        writer.write_all(serialized.as_bytes())?
+        Ok(Self {
+            prefix: prefix.to_string(),
+            width,
+            suffix: suffix.to_string(),
+        })
     }
 
-    if pretty {
-        writer.write_all(b"\n")?;
+    fn path(&self, index: usize) -> PathBuf {
+        format!(
+            "{}{:0width$}{}",
+            self.prefix,
+            index,
+            self.suffix,
+            width = self.width
+        )
+        .into()
     }
+}
 
-    writer.write_all(b"]")?;
+/// Total size of the input files, if they're all uncompressed files. Pipes,
+/// such as those of process substitution, have no size, which is warned
+/// about since the input looks like a file.
+fn input_size(inputs: &[Option<PathBuf>], gzip_in: bool) -> Option<u64> {
+    if gzip_in {
+        return None;
+    }
 
-    Ok(())
+    inputs
+        .iter()
+        .map(|input| {
+            let path = input
+                .as_deref()
+                .filter(|path| Compression::from_path(path).is_none())?;
+
+            if !InputSource::is_seekable(path) {
+                if !is_url(path) {
+                    log::warn!(
+                        "{} isn't a regular file, so --progress can't show how much is left",
+                        path.display()
+                    );
+                }
+
+                return None;
+            }
+
+            fs::metadata(path).ok().map(|metadata| metadata.len())
+        })
+        .sum()
 }
 
-fn main() -> std::io::Result<()> {
-    let args = Cli::parse();
+/// Reads the `--schema` file. One that isn't JSON is a usage error, like
+/// one that isn't a valid schema.
+fn read_schema(path: &Path) -> Result<serde_json::Value, Jl2jsError> {
+    let file = fs::File::open(path).map_err(|e| with_path(e, path))?;
 
-    let reader = match args.input {
-        Some(path) => InputSource::from_file(path)?,
-        None => InputSource::from_stdin(),
-    };
+    serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|e| Jl2jsError::Usage(format!("{}: {}", path.display(), e)))
+}
 
-    let writer = match args.output {
-        Some(path) => OutputSink::from_file(path)?,
-        None => OutputSink::from_stdout(),
-    };
+/// Notes errors reading an input, so that inputs given up on with
+/// `--continue-on-io-error` can be reported at the end.
+struct NoteFailures<R> {
+    inner: R,
+    name: String,
+    failures: Rc<RefCell<Vec<String>>>,
+}
 
-    process(reader, writer, args.pretty)
+impl<R: Read> Read for NoteFailures<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf).inspect_err(|e| {
+            if e.kind() != ErrorKind::Interrupted {
+                let failure = format!("{}: {}", self.name, e);
+                self.failures.borrow_mut().push(failure);
+            }
+        })
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
+/// Prints the inputs given up on with `--continue-on-io-error`, returning
+/// whether there were any.
+fn report_failures(failures: &[String], continued: bool) -> bool {
+    if !continued || failures.is_empty() {
+        return false;
+    }
 
-    #[test]
-    fn test_process() {
-        let input = r#"{"foo": "bar"}
-{"foo": "baz"}"#;
+    for failure in failures {
+        eprintln!("jl2js: skipped {}", failure);
+    }
 
-        let mut output = Vec::new();
-        process(Cursor::new(input), &mut output, false).unwrap();
+    eprintln!("jl2js: {} inputs could not be read", failures.len());
 
-        let expected_output = b"[{\"foo\":\"bar\"},{\"foo\":\"baz\"}]";
-        assert_eq!(output, expected_output);
+    true
+}
+
+/// Treats the output being closed early, as by `jl2js | head`, as success.
+fn ignore_broken_pipe(result: std::io::Result<()>) -> std::io::Result<()> {
+    match result {
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => result,
     }
+}
 
-    #[test]
-    fn test_process_pretty() {
-        let input = r#"{"foo": "bar"}
-{"foo": "baz"}"#;
+fn run(args: Cli) -> Result<ExitCode, Jl2jsError> {
+    let gzip_out = args.gzip_out || args.output.as_deref().is_some_and(is_gzip);
 
-        let mut output = Vec::new();
-        process(Cursor::new(input), &mut output, true).unwrap();
+    if args.append && gzip_out {
+        return Err(Jl2jsError::Usage(
+            "--append can't add to a gzip file".to_string(),
+        ));
+    }
+
+    if args.split.is_some() && gzip_out {
+        return Err(Jl2jsError::Usage(
+            "--split can't write gzip files".to_string(),
+        ));
+    }
+
+    let mut inputs = Vec::new();
+
+    for path in &args.input {
+        if is_url(path) {
+            if !cfg!(feature = "http") {
+                return Err(Jl2jsError::Usage(format!(
+                    "{}: reading URLs needs jl2js built with --features http",
+                    path.display()
+                )));
+            }
 
-        let expected_output = b"[\n{\n  \"foo\": \"bar\"\n},\n{\n  \"foo\": \"baz\"\n}\n]";
+            inputs.push(Some(path.clone()));
+        } else if path.is_dir() {
+            inputs.extend(jsonl_files(path, args.glob.as_ref())?.into_iter().map(Some));
+        } else {
+            let compressed = matches!(
+                Compression::from_path(path),
+                Some(Compression::Zstd | Compression::Bzip2)
+            );
 
-        assert_eq!(output, expected_output,);
+            if compressed && !cfg!(feature = "compression") {
+                return Err(Jl2jsError::Usage(format!(
+                    "{}: reading zstd and bzip2 needs jl2js built with --features compression",
+                    path.display()
+                )));
+            }
+
+            // Check every input up front, before any output is written.
+            if !args.continue_on_io_error {
+                fs::metadata(path).map_err(|e| with_path(e, path))?;
+            }
+            inputs.push(Some(path.clone()));
+        }
+    }
+
+    if args.input.is_empty() {
+        inputs.push(None);
     }
 
-    #[test]
-    fn test_invalid_json() {
-        let input = r#"{"foo": "bar"}{"foo": "baz"#; // Malformed JSON
+    if args.reverse {
+        let [input] = <[_; 1]>::try_from(inputs)
+            .map_err(|_| Jl2jsError::Usage("--reverse needs a single input file".to_string()))?;
+
+        let (mut writer, _) = open_output(
+            args.output,
+            false,
+            args.no_clobber,
+            gzip_out,
+            args.background_compression,
+            None,
+        )?;
+
+        match json_to_jsonl(
+            open_input(input, args.gzip_in)?,
+            &mut writer,
+            args.final_newline,
+        ) {
+            Err(Jl2jsError::Io(e)) => ignore_broken_pipe(Err(e))?,
+            result => result?,
+        }
+
+        ignore_broken_pipe(writer.finish())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let format = if args.ndjson || args.follow {
+        Format::Ndjson
+    } else if args.output_seq {
+        Format::JsonSeq
+    } else {
+        args.format
+            .or_else(|| {
+                let path = args.output.as_deref().filter(|_| args.out_ext_infer)?;
+                format_from_path(path)
+            })
+            .unwrap_or_default()
+    };
+
+    let indent = match args.indent {
+        Some(n) => Some(" ".repeat(n)),
+        None if args.indent_tabs => Some("\t".to_string()),
+        None => None,
+    };
+
+    let key_by = args.key_by.map(|field| KeyBy {
+        field,
+        on_duplicate: args.on_duplicate,
+    });
 
-        let mut output = Vec::new();
-        let result = process(Cursor::new(input), &mut output, false);
+    let mut options = Options {
+        omit_final_newline: !args.final_newline,
+        format,
+        wrap: args.wrap,
+        defer_open: args.defer_open,
+        float_format: args.float_format,
+        ascii: args.ascii,
+        append: None,
+        extend_header: args.extend_header,
+        key_by,
+        group_by: args.group_by.map(|field| GroupBy {
+            field,
+            missing: args.group_missing,
+        }),
+        merge: args.merge.then_some(args.merge_arrays),
+        infer_schema: args.infer_schema,
+        pretty: args.pretty
+            || indent.is_some()
+            || args.pretty_depth.is_some()
+            || args.compact_scalar_arrays,
+        pretty_depth: args.pretty_depth,
+        compact_scalar_arrays: args.compact_scalar_arrays,
+        indent,
+        lenient: args.lenient,
+        collect_errors: args
+            .collect_errors
+            .as_ref()
+            .map(|path| fs::File::create(path).map_err(|e| with_path(e, path)))
+            .transpose()?
+            .map(ErrorLog::new),
+        require_uniform_keys: args.require_uniform_keys,
+        warn_duplicate_keys: args.warn_duplicate_keys,
+        ignore_trailing: args.lenient_trailing,
+        #[cfg(feature = "relaxed")]
+        relaxed: args.relaxed,
+        schema: args.schema.as_deref().map(read_schema).transpose()?,
+        trim: args.trim,
+        allow_comments: args.allow_comments,
+        input_format: args.input_format.unwrap_or_default(),
+        csv_infer_types: args.csv_infer_types,
+        whitespace_separated: args.whitespace_separated,
+        lines_as_strings: args.lines_as_strings,
+        delimiter: args.delimiter,
+        max_line_bytes: args.max_line_bytes,
+        max_records: args.max_records,
+        max_output_bytes: args.max_output_bytes,
+        max_depth: args.max_depth,
+        ascii_keys_only: args.ascii_keys_only,
+        unwrap_arrays: args.unwrap_arrays,
+        filter: args.filter,
+        add_fields: args.add_field,
+        add_timestamp: args.add_timestamp,
+        line_number_key: args.with_line_number,
+        keep_existing_fields: args.keep_existing_fields,
+        drop_nulls: args.drop_nulls,
+        normalize_newlines: args.normalize_newlines,
+        sort_keys: args.sort_keys,
+        canonical: args.canonical,
+        sample: args.sample,
+        seed: args.seed,
+        skip: args.skip,
+        skip_lines: args.skip_lines,
+        limit: args.limit,
+        extract: args.extract,
+        jsonpath: args.jsonpath,
+        dedup: match args.dedup_by {
+            Some(field) => Some(Dedup::Field(field)),
+            None => args.dedup.then_some(Dedup::Record),
+        },
+        coerce: args.coerce.unwrap_or_default(),
+        rename: args.rename,
+        rename_overwrite: args.rename_overwrite,
+        select: args.select,
+        drop: args.drop,
+        flatten: args.flatten.then_some(args.flatten_sep),
+        passthrough: args.passthrough,
+        ensure_object: args.ensure_object,
+        wrap_scalars: args.wrap_scalars,
+        skip_non_objects: args.skip_non_objects,
+        jobs: args.jobs,
+        continue_on_io_error: args.continue_on_io_error,
+        stop: None,
+        flush: args.flush || args.follow || inputs.iter().flatten().any(|path| is_fifo(path)),
+        read_buffer_size: args.read_buffer,
+        buffer_size: args.buffer_size,
+        compact: args.compact,
+    };
 
-        assert!(result.is_err(), "Process should error on invalid JSON");
+    if let Some(dir) = &args.watch {
+        watch::watch(dir, args.glob.as_ref(), &options)?;
+        return Ok(ExitCode::SUCCESS);
     }
 
-    #[test]
-    fn test_empty_input() {
-        let input = "";
+    let progress = args
+        .progress
+        .then(|| Progress::new(input_size(&inputs, args.gzip_in)));
 
-        let mut output = Vec::new();
-        let result = process(Cursor::new(input), &mut output, false);
+    if args.follow && !matches!(inputs.as_slice(), [Some(path)] if !path.is_dir() && !is_url(path))
+    {
+        return Err(Jl2jsError::Usage(
+            "--follow needs a single input file".to_string(),
+        ));
+    }
+
+    let read_once = inputs.iter().find(
+        |input| !matches!(input, Some(path) if !is_url(path) && InputSource::is_seekable(path)),
+    );
 
-        assert!(
-            result.is_ok(),
-            "Process should handle empty input without error"
+    if let (Some(_), Some(input)) = (args.pretty_if_under, read_once) {
+        let name = match input {
+            Some(path) => path.display().to_string(),
+            None => "stdin".to_string(),
+        };
+
+        log::warn!(
+            "{} can't be read twice to count its records, so --pretty-if-under is ignored",
+            name
         );
-        assert_eq!(
-            output, b"[]",
-            "Output should be an empty JSON array for empty input"
+    } else if let Some(threshold) = args.pretty_if_under {
+        // Both passes have to sample the same records.
+        if options.sample.is_some() {
+            options.seed.get_or_insert_with(rand::random);
+        }
+
+        // Problems are reported by the pass that writes the output.
+        let counting = Options {
+            collect_errors: None,
+            warn_duplicate_keys: false,
+            ..options.clone()
+        };
+        let readers = inputs
+            .iter()
+            .map(|input| open_input(input.clone(), args.gzip_in));
+        let stats = convert_all(readers, std::io::sink(), &counting)?;
+
+        options.pretty = stats.records < threshold;
+    }
+
+    let failures = Rc::new(RefCell::new(Vec::new()));
+
+    let readers = inputs.into_iter().map(|input| {
+        let name = match &input {
+            Some(path) => path.display().to_string(),
+            None => "stdin".to_string(),
+        };
+
+        let source = match input {
+            Some(path) if args.follow => InputSource::follow(path),
+            input => open_input(input, args.gzip_in),
+        }
+        .inspect_err(|e| failures.borrow_mut().push(e.to_string()))?;
+
+        let reader: Box<dyn Read> = match &progress {
+            Some(progress) => Box::new(ProgressReader::new(source, progress.clone())),
+            None => Box::new(source),
+        };
+
+        if !args.continue_on_io_error {
+            return Ok(reader);
+        }
+
+        Ok(Box::new(NoteFailures {
+            inner: reader,
+            name,
+            failures: failures.clone(),
+        }) as Box<dyn Read>)
+    });
+
+    if args.count_only {
+        let counts = count_all(readers, &options)?;
+
+        if let Some(progress) = &progress {
+            progress.borrow().finish();
+        }
+
+        println!(
+            "{}",
+            serde_json::to_string(&counts).map_err(std::io::Error::from)?
+        );
+
+        return Ok(
+            match report_failures(&failures.borrow(), args.continue_on_io_error) {
+                true => ExitCode::from(EXIT_IO),
+                false => ExitCode::SUCCESS,
+            },
         );
     }
+
+    if args.check {
+        let report = check_all(readers, &options)?;
+
+        if let Some(progress) = &progress {
+            progress.borrow().finish();
+        }
+
+        return Ok(match report.first_error {
+            Some(e) => {
+                eprintln!(
+                    "jl2js: {} invalid lines, the first at {}",
+                    report.invalid, e
+                );
+                ExitCode::from(EXIT_INVALID)
+            }
+            None if report_failures(&failures.borrow(), args.continue_on_io_error) => {
+                ExitCode::from(EXIT_IO)
+            }
+            None => ExitCode::SUCCESS,
+        });
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+
+    if !args.follow {
+        let handler_stop = stop.clone();
+
+        // The first Ctrl-C lets the conversion finish the output; a second
+        // one gives up on it.
+        ctrlc::set_handler(move || {
+            if handler_stop.swap(true, Ordering::Relaxed) {
+                std::process::exit(EXIT_INTERRUPTED.into());
+            }
+        })
+        .map_err(std::io::Error::other)?;
+
+        options.stop = Some(stop.clone());
+    }
+
+    let stats = match (args.split, args.output) {
+        (Some(records), Some(output)) => {
+            let template = ChunkTemplate::parse(&output)?;
+
+            convert_chunks(
+                readers,
+                |index| create_chunk(template.path(index), args.no_clobber),
+                records,
+                &options,
+            )?
+        }
+        (_, output) => {
+            let checksum = args.manifest.as_ref().map(|_| Checksum::new());
+            let (mut writer, append) = open_output(
+                output,
+                args.append,
+                args.no_clobber,
+                gzip_out,
+                args.background_compression,
+                checksum.as_ref(),
+            )?;
+            options.append = append;
+
+            let stats = convert_all(readers, &mut writer, &options)?;
+            ignore_broken_pipe(writer.finish())?;
+
+            if let (Some(path), Some(checksum)) = (&args.manifest, checksum) {
+                let manifest = serde_json::to_vec_pretty(&checksum.manifest(stats.records))
+                    .map_err(std::io::Error::from)?;
+                fs::write(path, manifest).map_err(|e| with_path(e, path))?;
+            }
+
+            stats
+        }
+    };
+
+    if let Some(progress) = &progress {
+        progress.borrow().finish();
+    }
+
+    if args.stats {
+        eprintln!(
+            "{} records written, {} skipped, {} bytes out",
+            stats.records, stats.skipped, stats.bytes
+        );
+    } else if stats.skipped > 0 {
+        eprintln!("skipped {} invalid records", stats.skipped);
+    }
+
+    if report_failures(&failures.borrow(), args.continue_on_io_error) {
+        return Ok(ExitCode::from(EXIT_IO));
+    }
+
+    if stop.load(Ordering::Relaxed) {
+        return Ok(ExitCode::from(EXIT_INTERRUPTED));
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The exit status for a failed run.
+fn exit_code(e: &Jl2jsError) -> u8 {
+    match e {
+        Jl2jsError::Usage(_) => EXIT_USAGE,
+        Jl2jsError::Io(_) => EXIT_IO,
+        _ => EXIT_INVALID,
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match config::parse(std::env::args_os().collect()) {
+        Ok(args) => args,
+        Err(e) => {
+            let _ = e.print();
+
+            // --help and --version are printed to stdout.
+            return if e.use_stderr() {
+                ExitCode::from(EXIT_USAGE)
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
+
+    let level = match args.verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+
+    // Logs go to stderr, so they never mix with output on stdout.
+    env_logger::Builder::new()
+        .filter_module("jl2js", level)
+        .parse_default_env()
+        .init();
+
+    match run(args) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("jl2js: {}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
 }
@@ -0,0 +1,99 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+
+/// A summary of finished output, for checking it wasn't truncated or
+/// altered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Manifest {
+    pub records: usize,
+    /// Length of the output in bytes, after any compression.
+    pub bytes: u64,
+    /// SHA-256 of the output, in lowercase hex.
+    pub sha256: String,
+}
+
+/// Hashes and counts the bytes written to an [`OutputSink`] made with
+/// [`OutputSink::checksummed`]. Clones share the same totals.
+///
+/// [`OutputSink`]: crate::OutputSink
+/// [`OutputSink::checksummed`]: crate::OutputSink::checksummed
+#[derive(Debug, Clone, Default)]
+pub struct Checksum(Arc<Mutex<Totals>>);
+
+#[derive(Debug, Default)]
+struct Totals {
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl Checksum {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn update(&self, written: &[u8]) {
+        let mut totals = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        totals.hasher.update(written);
+        totals.bytes += written.len() as u64;
+    }
+
+    /// Summarizes what's been written so far, which held `records` records.
+    pub fn manifest(&self, records: usize) -> Manifest {
+        let totals = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let sha256 = totals
+            .hasher
+            .clone()
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        Manifest {
+            records,
+            bytes: totals.bytes,
+            sha256,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{convert, Options, OutputSink};
+    use std::fs;
+
+    #[test]
+    fn test_manifest_matches_output() {
+        let path =
+            std::env::temp_dir().join(format!("jl2js-{}-manifest.json.gz", std::process::id()));
+        let checksum = Checksum::new();
+
+        let mut sink = OutputSink::from_file(path.clone())
+            .unwrap()
+            .checksummed(checksum.clone())
+            .gzip();
+        let stats = convert(
+            &b"{\"a\":1}\n{\"a\":2}\n"[..],
+            &mut sink,
+            &Options::default(),
+        )
+        .unwrap();
+        sink.finish().unwrap();
+
+        let written = fs::read(&path).unwrap();
+        let manifest = checksum.manifest(stats.records);
+
+        assert_eq!(manifest.records, 2);
+        assert_eq!(manifest.bytes, written.len() as u64);
+        assert_eq!(
+            manifest.sha256,
+            Sha256::digest(&written)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+}
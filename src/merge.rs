@@ -0,0 +1,78 @@
+use serde_json::{Map, Value};
+
+/// How arrays are combined when records are deep-merged into one object.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum MergeArrays {
+    /// A later array replaces an earlier one, like any other value.
+    #[default]
+    Replace,
+    /// A later array is appended to an earlier one.
+    Concat,
+}
+
+/// Merges `source` into `target`. Objects present in both are merged
+/// recursively; for anything else the value from `source` wins, except for
+/// two arrays under [`MergeArrays::Concat`]. New keys are added at the end.
+pub(crate) fn deep_merge(
+    target: &mut Map<String, Value>,
+    source: Map<String, Value>,
+    arrays: MergeArrays,
+) {
+    for (key, value) in source {
+        match (target.get_mut(&key), value) {
+            (Some(Value::Object(existing)), Value::Object(value)) => {
+                deep_merge(existing, value, arrays)
+            }
+            (Some(Value::Array(existing)), Value::Array(value))
+                if arrays == MergeArrays::Concat =>
+            {
+                existing.extend(value)
+            }
+            (Some(existing), value) => *existing = value,
+            (None, value) => {
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn merged(records: Vec<Value>, arrays: MergeArrays) -> Value {
+        let mut target = Map::new();
+
+        for record in records {
+            let Value::Object(record) = record else {
+                panic!("not an object");
+            };
+            deep_merge(&mut target, record, arrays);
+        }
+
+        Value::Object(target)
+    }
+
+    #[test]
+    fn test_deep_merge() {
+        let records = vec![
+            json!({"db": {"host": "localhost", "port": 5432}, "tags": ["a"], "debug": false}),
+            json!({"db": {"port": 6543, "user": "app"}, "tags": ["b"], "debug": {"level": 2}}),
+        ];
+
+        assert_eq!(
+            merged(records.clone(), MergeArrays::Replace),
+            json!({
+                "db": {"host": "localhost", "port": 6543, "user": "app"},
+                "tags": ["b"],
+                "debug": {"level": 2}
+            })
+        );
+        assert_eq!(
+            merged(records, MergeArrays::Concat)["tags"],
+            json!(["a", "b"])
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use crate::writer::{CountingWriter, NativeNumbers};
+use crate::Jl2jsError;
+use serde_json::Value;
+use std::io::{ErrorKind, Write};
+
+/// Writes records as a MessagePack array, or as a map from their keys for
+/// output keyed by a field. The length has to be written before them, so
+/// they're encoded into memory and only written once finished.
+pub(crate) struct MsgpackWriter<W: Write> {
+    writer: CountingWriter<W>,
+    keyed: bool,
+    encoded: Vec<u8>,
+    records: usize,
+}
+
+impl<W: Write> MsgpackWriter<W> {
+    pub(crate) fn new(writer: W, keyed: bool) -> Self {
+        Self {
+            writer: CountingWriter::new(writer),
+            keyed,
+            encoded: Vec::new(),
+            records: 0,
+        }
+    }
+
+    pub(crate) fn write_record(&mut self, value: &Value) -> Result<(), Jl2jsError> {
+        self.encode(value)?;
+        self.records += 1;
+
+        Ok(())
+    }
+
+    /// Writes a record under `key`, for output keyed by a field.
+    pub(crate) fn write_entry(&mut self, key: &str, value: &Value) -> Result<(), Jl2jsError> {
+        rmp::encode::write_str(&mut self.encoded, key).map_err(std::io::Error::other)?;
+        self.write_record(value)
+    }
+
+    fn encode(&mut self, value: &Value) -> std::io::Result<()> {
+        rmp_serde::encode::write(&mut self.encoded, &NativeNumbers(value))
+            .map_err(std::io::Error::other)
+    }
+
+    pub(crate) fn records(&self) -> usize {
+        self.records
+    }
+
+    /// The bytes written so far, which is none until finished.
+    pub(crate) fn bytes(&self) -> u64 {
+        self.writer.count()
+    }
+
+    pub(crate) fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub(crate) fn finish(&mut self) -> std::io::Result<()> {
+        let len = u32::try_from(self.records).map_err(|_| {
+            std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "MessagePack output is limited to 4294967295 records",
+            )
+        })?;
+
+        let header = match self.keyed {
+            true => rmp::encode::write_map_len(&mut self.writer, len),
+            false => rmp::encode::write_array_len(&mut self.writer, len),
+        };
+        header.map_err(std::io::Error::from)?;
+
+        self.writer.write_all(&std::mem::take(&mut self.encoded))?;
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,579 @@
+use crate::{Checksum, ExistingArray};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File, OpenOptions};
+use std::io::{stdout, ErrorKind, Read, Seek, SeekFrom, Stdout, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// Bytes collected before they're handed to a background thread.
+const BACKGROUND_CHUNK: usize = 128 * 1024;
+
+/// Chunks that can be waiting for a background thread before writing to
+/// it blocks.
+const BACKGROUND_QUEUE: usize = 8;
+
+/// Where JSON output is written to.
+pub enum OutputSink {
+    File(File),
+    Stdout(Stdout),
+    Gzip(Box<GzEncoder<OutputSink>>),
+    /// A temporary file that replaces another once finished.
+    Replace(Box<Replacement>),
+    /// Hashes what's written on its way to the inner sink.
+    Checksummed(Box<OutputSink>, Checksum),
+    /// Writes to the inner sink on another thread.
+    Background(Box<Background>),
+}
+
+impl OutputSink {
+    /// Writes to the file at `path`. Output goes to a temporary file next to
+    /// it that only replaces `path` once the sink is finished, so `path` is
+    /// never left half written. If the sink is dropped before that, as when
+    /// a conversion fails, `path` is left as it was.
+    ///
+    /// If `path` is something other than a regular file, like a named pipe
+    /// or `/dev/stdout`, it's written to directly instead, since replacing
+    /// it would cut off whatever reads from it.
+    pub fn from_file(path: PathBuf) -> std::io::Result<Self> {
+        if !replaceable(&path) {
+            return Ok(Self::File(File::create(path)?));
+        }
+
+        Ok(Self::Replace(Box::new(Replacement::new(path)?)))
+    }
+
+    /// Like [`OutputSink::from_file`], but fails with
+    /// [`ErrorKind::AlreadyExists`] if `path` already exists as a regular
+    /// file. `path` is claimed by creating it empty right away, and removed
+    /// again if the sink is dropped before it's finished. Anything else at
+    /// `path`, like a named pipe, is written to directly, as writing to it
+    /// doesn't overwrite anything.
+    pub fn from_new_file(path: PathBuf) -> std::io::Result<Self> {
+        if !replaceable(&path) {
+            return Ok(Self::File(File::create(path)?));
+        }
+
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        let mut replacement = Replacement::new(path)?;
+        replacement.claimed = true;
+
+        Ok(Self::Replace(Box::new(replacement)))
+    }
+
+    pub fn from_stdout() -> Self {
+        Self::Stdout(stdout())
+    }
+
+    /// Opens the JSON array in `path` for appending. Its contents up to the
+    /// closing bracket are copied to a temporary file, written to by the
+    /// returned sink, which replaces `path` when the sink is finished.
+    ///
+    /// Returns `None` alongside the sink if `path` is missing or blank, in
+    /// which case a new array has to be written. That's also the case when
+    /// `path` isn't a regular file, like a named pipe, which has no array to
+    /// read and is written to directly.
+    pub fn append(path: PathBuf) -> std::io::Result<(Self, Option<ExistingArray>)> {
+        if !replaceable(&path) {
+            return Ok((Self::File(File::create(path)?), None));
+        }
+
+        let mut replacement = Replacement::new(path)?;
+
+        let existing = match File::open(&replacement.path) {
+            Ok(file) => copy_open_array(file, &mut replacement.file)
+                .map_err(|e| with_path(e, &replacement.path))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+
+        Ok((Self::Replace(Box::new(replacement)), existing))
+    }
+
+    /// Hashes and counts every byte written to this sink into `checksum`.
+    /// Wrap the sink in [`OutputSink::gzip`] afterwards, not before, to
+    /// checksum the compressed bytes.
+    pub fn checksummed(self, checksum: Checksum) -> Self {
+        Self::Checksummed(Box::new(self), checksum)
+    }
+
+    /// Compresses everything written to this sink as gzip.
+    pub fn gzip(self) -> Self {
+        Self::Gzip(Box::new(GzEncoder::new(self, Compression::default())))
+    }
+
+    /// Writes to this sink on a thread of its own, so that whatever it does
+    /// with the bytes, like compressing them, overlaps with producing them.
+    /// The bytes written are the same either way. Errors writing may only
+    /// be returned by a later write, flush or [`OutputSink::finish`].
+    pub fn in_background(self) -> Self {
+        Self::Background(Box::new(Background::new(self)))
+    }
+
+    /// Flushes the sink and writes any trailing bytes, such as the gzip footer.
+    pub fn finish(self) -> std::io::Result<()> {
+        match self {
+            OutputSink::Gzip(encoder) => encoder.finish()?.finish(),
+            OutputSink::Background(background) => background.finish()?.finish(),
+            OutputSink::Replace(replacement) => replacement.commit(),
+            OutputSink::Checksummed(sink, _) => sink.finish(),
+            mut sink => sink.flush(),
+        }
+    }
+}
+
+impl Write for OutputSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputSink::File(file) => file.write(buf),
+            OutputSink::Stdout(sink) => sink.write(buf),
+            OutputSink::Gzip(encoder) => encoder.write(buf),
+            OutputSink::Replace(replacement) => replacement.file.write(buf),
+            OutputSink::Checksummed(sink, checksum) => {
+                let written = sink.write(buf)?;
+                checksum.update(&buf[..written]);
+                Ok(written)
+            }
+            OutputSink::Background(background) => background.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputSink::File(file) => file.flush(),
+            OutputSink::Stdout(sink) => sink.flush(),
+            OutputSink::Gzip(encoder) => encoder.flush(),
+            OutputSink::Replace(replacement) => replacement.file.flush(),
+            OutputSink::Checksummed(sink, _) => sink.flush(),
+            OutputSink::Background(background) => background.flush(),
+        }
+    }
+}
+
+/// What a [`Background`] thread is asked to do.
+enum Message {
+    Write(Vec<u8>),
+    Flush,
+}
+
+/// A sink written to by a thread of its own. Writes are collected into
+/// chunks that the thread is sent over a bounded channel, so a slow sink
+/// holds up the writer once a few chunks are waiting.
+pub struct Background {
+    chunk: Vec<u8>,
+    sender: Option<SyncSender<Message>>,
+    thread: Option<JoinHandle<std::io::Result<OutputSink>>>,
+}
+
+impl Background {
+    fn new(mut sink: OutputSink) -> Self {
+        let (sender, receiver): (_, Receiver<Message>) = mpsc::sync_channel(BACKGROUND_QUEUE);
+
+        let thread = thread::spawn(move || {
+            for message in receiver {
+                match message {
+                    Message::Write(chunk) => sink.write_all(&chunk)?,
+                    Message::Flush => sink.flush()?,
+                }
+            }
+
+            Ok(sink)
+        });
+
+        Self {
+            chunk: Vec::with_capacity(BACKGROUND_CHUNK),
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    fn send(&mut self, message: Message) -> std::io::Result<()> {
+        let sent = self.sender.as_ref().map(|sender| sender.send(message));
+
+        match sent {
+            Some(Ok(())) => Ok(()),
+            // The thread only stops early when writing failed.
+            _ => Err(self
+                .join()
+                .err()
+                .unwrap_or_else(|| std::io::Error::other("output thread stopped unexpectedly"))),
+        }
+    }
+
+    fn send_chunk(&mut self) -> std::io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(BACKGROUND_CHUNK));
+        self.send(Message::Write(chunk))
+    }
+
+    /// Waits for the thread to write everything it was sent, returning the
+    /// sink it wrote to.
+    fn join(&mut self) -> std::io::Result<OutputSink> {
+        self.sender = None;
+
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => Err(std::io::Error::other("output thread already stopped")),
+        }
+    }
+
+    fn finish(mut self) -> std::io::Result<OutputSink> {
+        self.send_chunk()?;
+        self.join()
+    }
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+
+        if self.chunk.len() >= BACKGROUND_CHUNK {
+            self.send_chunk()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send_chunk()?;
+        self.send(Message::Flush)
+    }
+}
+
+impl Drop for Background {
+    fn drop(&mut self) {
+        // Let the thread drop the sink, as when an unfinished replacement
+        // file is removed, before the process can exit.
+        let _ = self.join();
+    }
+}
+
+/// Whether `path` is missing or a regular file, and so can be replaced by
+/// renaming another file over it.
+fn replaceable(path: &Path) -> bool {
+    fs::metadata(path).map_or(true, |metadata| metadata.is_file())
+}
+
+/// A file being written next to `path` that is renamed over it once
+/// complete, so `path` is never left half written. The temporary file is
+/// removed if it's dropped before then. It gets the permissions of the file
+/// it replaces, and a name of its own, so that runs writing to the same
+/// `path` don't write to the same temporary file.
+pub struct Replacement {
+    file: File,
+    temp: PathBuf,
+    path: PathBuf,
+    committed: bool,
+    /// Whether `path` was created empty to claim it, and so is removed too
+    /// if this is dropped before it's complete.
+    claimed: bool,
+}
+
+impl Replacement {
+    fn new(path: PathBuf) -> std::io::Result<Self> {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(
+            ".{}-{:08x}.jl2js-tmp",
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        let temp = path.with_file_name(name);
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+
+        if let Ok(metadata) = fs::metadata(&path) {
+            if let Err(e) = file.set_permissions(metadata.permissions()) {
+                let _ = fs::remove_file(&temp);
+                return Err(e);
+            }
+        }
+
+        Ok(Self {
+            file,
+            temp,
+            path,
+            committed: false,
+            claimed: false,
+        })
+    }
+
+    fn commit(mut self) -> std::io::Result<()> {
+        self.file.sync_all()?;
+        fs::rename(&self.temp, &self.path)?;
+        self.committed = true;
+
+        Ok(())
+    }
+}
+
+impl Drop for Replacement {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp);
+
+            if self.claimed {
+                let _ = fs::remove_file(&self.path);
+            }
+        }
+    }
+}
+
+fn with_path(e: std::io::Error, path: &Path) -> std::io::Error {
+    std::io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+/// Copies the JSON array in `file` to `out`, leaving off its closing bracket
+/// and the whitespace before it. The array's elements aren't checked.
+fn copy_open_array(mut file: File, out: &mut File) -> std::io::Result<Option<ExistingArray>> {
+    let len = file.metadata()?.len();
+    let not_an_array = || std::io::Error::new(ErrorKind::InvalidData, "not a JSON array");
+
+    let open = match find_non_whitespace(&mut file, 0..len)? {
+        Some((open, b'[')) => open,
+        Some(_) => return Err(not_an_array()),
+        None => return Ok(None),
+    };
+
+    let Some((close, b']')) = find_non_whitespace(&mut file, (open + 1..len).rev())? else {
+        return Err(not_an_array());
+    };
+
+    let (end, existing) = match find_non_whitespace(&mut file, (open + 1..close).rev())? {
+        Some((last, _)) => (last + 1, ExistingArray::NonEmpty),
+        None => (open + 1, ExistingArray::Empty),
+    };
+
+    file.seek(SeekFrom::Start(0))?;
+    std::io::copy(&mut file.take(end), out)?;
+
+    Ok(Some(existing))
+}
+
+/// Returns the first of `offsets` in `file` that holds a byte other than
+/// JSON whitespace, along with that byte.
+fn find_non_whitespace(
+    file: &mut File,
+    offsets: impl Iterator<Item = u64>,
+) -> std::io::Result<Option<(u64, u8)>> {
+    let mut byte = [0];
+
+    for offset in offsets {
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut byte)?;
+
+        if !matches!(byte[0], b' ' | b'\t' | b'\n' | b'\r') {
+            return Ok(Some((offset, byte[0])));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InputSource;
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let path = std::env::temp_dir().join(format!("jl2js-{}.json.gz", std::process::id()));
+
+        let mut sink = OutputSink::from_file(path.clone()).unwrap().gzip();
+        sink.write_all(b"[{\"foo\":\"bar\"}]").unwrap();
+        sink.finish().unwrap();
+
+        let mut contents = String::new();
+        InputSource::from_file(path.clone())
+            .unwrap()
+            .gzip()
+            .read_to_string(&mut contents)
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(contents, "[{\"foo\":\"bar\"}]");
+    }
+
+    #[test]
+    fn test_append() {
+        let path = std::env::temp_dir().join(format!("jl2js-{}-append.json", std::process::id()));
+
+        for (existing, expected, found) in [
+            ("[\n  1\n]\n", "[\n  1,2]", Some(ExistingArray::NonEmpty)),
+            (" [ ] ", " [2]", Some(ExistingArray::Empty)),
+            ("", "[2]", None),
+        ] {
+            std::fs::write(&path, existing).unwrap();
+
+            let (mut sink, existing) = OutputSink::append(path.clone()).unwrap();
+            assert_eq!(existing, found);
+
+            sink.write_all(match existing {
+                Some(ExistingArray::NonEmpty) => ",2]".as_bytes(),
+                Some(ExistingArray::Empty) => "2]".as_bytes(),
+                None => "[2]".as_bytes(),
+            })
+            .unwrap();
+            sink.finish().unwrap();
+
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+        }
+
+        std::fs::write(&path, "{}").unwrap();
+        assert!(OutputSink::append(path.clone()).is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            std::fs::write(&path, "[1]").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+            let (mut sink, _) = OutputSink::append(path.clone()).unwrap();
+            sink.write_all(b",2]").unwrap();
+            sink.finish().unwrap();
+
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "[1,2]");
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+
+            // There's no array to append to in a device or pipe.
+            let (mut sink, existing) = OutputSink::append(PathBuf::from("/dev/null")).unwrap();
+            assert_eq!(existing, None);
+            sink.write_all(b"[2]").unwrap();
+            sink.finish().unwrap();
+            assert!(!std::fs::metadata("/dev/null").unwrap().is_file());
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_is_replaced_when_finished() {
+        let path = std::env::temp_dir().join(format!("jl2js-{}-atomic.json", std::process::id()));
+        std::fs::write(&path, "original").unwrap();
+
+        let mut sink = OutputSink::from_file(path.clone()).unwrap();
+        let result = crate::convert(
+            "{\"a\":1}\nnot json\n".as_bytes(),
+            &mut sink,
+            &Default::default(),
+        );
+        assert!(result.is_err());
+        drop(sink);
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "original");
+        assert_eq!(temp_files(&path), 0);
+
+        let mut sink = OutputSink::from_file(path.clone()).unwrap();
+        crate::convert("{\"a\":1}\n".as_bytes(), &mut sink, &Default::default()).unwrap();
+        sink.finish().unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[{\"a\":1}]");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// How many temporary files for `path` are left next to it.
+    fn temp_files(path: &Path) -> usize {
+        let name = path.file_name().unwrap().to_str().unwrap();
+
+        std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter_map(|entry| entry.unwrap().file_name().into_string().ok())
+            .filter(|file| file.starts_with(name) && file.ends_with(".jl2js-tmp"))
+            .count()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replacement_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("jl2js-{}-mode.json", std::process::id()));
+        std::fs::write(&path, "original").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+        // Runs writing to the same path at once don't share a temporary file.
+        let mut first = OutputSink::from_file(path.clone()).unwrap();
+        let mut second = OutputSink::from_file(path.clone()).unwrap();
+        assert_eq!(temp_files(&path), 2);
+        first.write_all(b"[1]").unwrap();
+        second.write_all(b"[2]").unwrap();
+        first.finish().unwrap();
+        second.finish().unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[2]");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        assert_eq!(temp_files(&path), 0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_gzip_in_background() {
+        let input: String = (0..20_000)
+            .map(|i| format!("{{\"id\":{},\"name\":\"user{}\"}}\n", i, i % 7))
+            .collect();
+        let path = |name: &str| {
+            std::env::temp_dir().join(format!("jl2js-{}-{}.json.gz", std::process::id(), name))
+        };
+
+        for (name, background) in [("inline", false), ("background", true)] {
+            let mut sink = OutputSink::from_file(path(name)).unwrap().gzip();
+
+            if background {
+                sink = sink.in_background();
+            }
+
+            crate::convert(input.as_bytes(), &mut sink, &Default::default()).unwrap();
+            sink.finish().unwrap();
+        }
+
+        let inline = std::fs::read(path("inline")).unwrap();
+        assert_eq!(std::fs::read(path("background")).unwrap(), inline);
+
+        std::fs::remove_file(path("inline")).unwrap();
+        std::fs::remove_file(path("background")).unwrap();
+    }
+
+    #[test]
+    fn test_new_file_is_not_clobbered() {
+        let path = std::env::temp_dir().join(format!("jl2js-{}-new.json", std::process::id()));
+        std::fs::write(&path, "original").unwrap();
+
+        let error = OutputSink::from_new_file(path.clone()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "original");
+
+        std::fs::remove_file(&path).unwrap();
+        drop(OutputSink::from_new_file(path.clone()).unwrap());
+        assert!(!path.exists());
+
+        let mut sink = OutputSink::from_new_file(path.clone()).unwrap();
+        sink.write_all(b"[]").unwrap();
+        sink.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]");
+
+        std::fs::remove_file(path).unwrap();
+
+        // Writing to a device or pipe doesn't overwrite anything.
+        #[cfg(unix)]
+        {
+            let mut sink = OutputSink::from_new_file(PathBuf::from("/dev/null")).unwrap();
+            sink.write_all(b"[]").unwrap();
+            sink.finish().unwrap();
+            assert!(!std::fs::metadata("/dev/null").unwrap().is_file());
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::cell::RefCell;
+use std::io::{stderr, IsTerminal, Read};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tracks how much input has been read and reports it to stderr, at most
+/// once per [`INTERVAL`].
+pub struct Progress {
+    /// Total size of the input, when it's known up front.
+    total: Option<u64>,
+    bytes: u64,
+    lines: u64,
+    started: Instant,
+    reported: Instant,
+    /// Reports overwrite each other on a terminal, and are one per line
+    /// otherwise.
+    terminal: bool,
+}
+
+impl Progress {
+    pub fn new(total: Option<u64>) -> Rc<RefCell<Self>> {
+        let now = Instant::now();
+
+        Rc::new(RefCell::new(Self {
+            total,
+            bytes: 0,
+            lines: 0,
+            started: now,
+            reported: now,
+            terminal: stderr().is_terminal(),
+        }))
+    }
+
+    fn advance(&mut self, read: &[u8]) {
+        self.bytes += read.len() as u64;
+        self.lines += read.iter().filter(|&&b| b == b'\n').count() as u64;
+
+        if self.reported.elapsed() >= INTERVAL {
+            self.reported = Instant::now();
+            self.report();
+        }
+    }
+
+    fn report(&self) {
+        let seconds = self.started.elapsed().as_secs_f64();
+        let rate = if seconds > 0.0 {
+            self.bytes as f64 / seconds
+        } else {
+            0.0
+        };
+
+        let mut line = format!(
+            "{} lines, {:.1} MB read, {:.1} MB/s",
+            self.lines,
+            self.bytes as f64 / 1e6,
+            rate / 1e6
+        );
+
+        if let Some(total) = self.total.filter(|&total| total > 0) {
+            line += &format!(" ({:.0}%)", self.bytes as f64 * 100.0 / total as f64);
+        }
+
+        if self.terminal {
+            eprint!("\r{}", line);
+        } else {
+            eprintln!("{}", line);
+        }
+    }
+
+    /// Reports the final totals.
+    pub fn finish(&self) {
+        self.report();
+
+        if self.terminal {
+            eprintln!();
+        }
+    }
+}
+
+/// Counts what's read through it towards a shared [`Progress`].
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Rc<RefCell<Progress>>,
+}
+
+impl<R> ProgressReader<R> {
+    pub fn new(inner: R, progress: Rc<RefCell<Progress>>) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.progress.borrow_mut().advance(&buf[..read]);
+
+        Ok(read)
+    }
+}
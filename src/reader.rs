@@ -0,0 +1,269 @@
+use crate::lines::{Line, LineReader};
+use crate::{Jl2jsError, Options};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::io::{BufReader, Read};
+use std::time::Instant;
+
+/// Capacity of the input buffer unless [`Options::read_buffer_size`] says
+/// otherwise. Larger than `BufReader`'s 8 KiB default, which takes many more
+/// reads to get through large records.
+pub(crate) const DEFAULT_READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Byte order mark some tools put at the start of UTF-8 files.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// How records are framed in the input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum InputFormat {
+    /// One JSON value per line.
+    #[default]
+    Jsonl,
+    /// A CSV table with a header row. Each row is read as an object with
+    /// the header's names as keys.
+    Csv,
+}
+
+#[cfg_attr(not(feature = "relaxed"), allow(unused_variables))]
+pub(crate) fn parse_line<T: DeserializeOwned>(
+    line: &str,
+    options: &Options,
+) -> serde_json::Result<T> {
+    #[cfg(feature = "relaxed")]
+    if options.relaxed {
+        return json5::from_str(line).map_err(serde::de::Error::custom);
+    }
+
+    if options.ignore_trailing {
+        // Unlike `from_str`, this doesn't check for anything after the
+        // value.
+        return T::deserialize(&mut serde_json::Deserializer::from_str(line));
+    }
+
+    serde_json::from_str(line)
+}
+
+/// Like [`parse_line`], logging how long parsing took at the trace level.
+pub(crate) fn parse_timed(
+    number: usize,
+    line: &str,
+    options: &Options,
+) -> serde_json::Result<Value> {
+    if !log::log_enabled!(log::Level::Trace) {
+        return parse_line(line, options);
+    }
+
+    let started = Instant::now();
+    let result = parse_line(line, options);
+    log::trace!("line {}: parsed in {:?}", number, started.elapsed());
+
+    result
+}
+
+/// Whether a line is a whole-line `#` or `//` comment.
+fn is_comment(line: &str) -> bool {
+    let line = line.trim_start();
+
+    line.starts_with('#') || line.starts_with("//")
+}
+
+/// Reads JSONL input one record at a time.
+///
+/// Each item is the value parsed from a line, or the error for that line,
+/// numbered from 1, including lines that aren't valid UTF-8. Blank lines
+/// are passed over. Reading carries on after a
+/// line that fails to parse; an error reading the input itself ends it.
+///
+/// Of [`Options`], only those about reading and parsing lines apply:
+/// `delimiter`, `max_line_bytes`, `skip_lines`, `trim`, `allow_comments`,
+/// `ignore_trailing` and `relaxed`.
+pub struct JsonlReader<R> {
+    lines: LineReader<BufReader<R>>,
+    options: Options,
+    /// Number of the last line read.
+    line: usize,
+    /// Number of blank lines passed over.
+    blank: usize,
+    /// Set once reading the input failed.
+    failed: bool,
+}
+
+impl<R: Read> JsonlReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, &Options::default())
+    }
+
+    pub fn with_options(reader: R, options: &Options) -> Self {
+        Self {
+            lines: LineReader::new(
+                BufReader::with_capacity(
+                    options.read_buffer_size.unwrap_or(DEFAULT_READ_BUFFER_SIZE),
+                    reader,
+                ),
+                options.delimiter,
+                options.max_line_bytes,
+            ),
+            options: options.clone(),
+            line: 0,
+            blank: 0,
+            failed: false,
+        }
+    }
+
+    /// Number of the last line read, counting blank ones.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Number of blank lines read so far.
+    pub(crate) fn blank_lines(&self) -> usize {
+        self.blank
+    }
+
+    /// Bytes read from the input so far.
+    pub(crate) fn bytes(&self) -> u64 {
+        self.lines.bytes()
+    }
+
+    /// Returns the next line worth parsing along with its number, without
+    /// parsing it.
+    pub(crate) fn next_line(&mut self) -> Option<Result<(usize, String), Jl2jsError>> {
+        while !self.failed {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e.into()));
+                }
+            };
+            self.line += 1;
+
+            if self.line <= self.options.skip_lines {
+                continue;
+            }
+
+            match self.accept(line) {
+                Ok(Some(line)) => return Some(Ok((self.line, line))),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        None
+    }
+
+    /// Returns the text of a line worth parsing, or `None` if it should be
+    /// passed over.
+    fn accept(&mut self, line: Line) -> Result<Option<String>, Jl2jsError> {
+        let mut data = match line {
+            Line::Data(data) => data,
+            Line::TooLong => {
+                return Err(Jl2jsError::LineTooLong {
+                    line: self.line,
+                    max: self.options.max_line_bytes.unwrap_or_default(),
+                })
+            }
+        };
+
+        if self.line == 1 && data.starts_with(UTF8_BOM) {
+            data.drain(..UTF8_BOM.len());
+        }
+
+        let mut line = String::from_utf8(data).map_err(|e| Jl2jsError::InvalidUtf8 {
+            line: self.line,
+            column: e.utf8_error().valid_up_to() + 1,
+        })?;
+
+        if self.options.trim {
+            line.truncate(line.trim_end().len());
+            line.drain(..line.len() - line.trim_start().len());
+        }
+
+        if line.trim().is_empty() {
+            self.blank += 1;
+            return Ok(None);
+        }
+
+        if self.options.allow_comments && is_comment(&line) {
+            return Ok(None);
+        }
+
+        Ok(Some(line))
+    }
+}
+
+impl<R: Read> Iterator for JsonlReader<R> {
+    type Item = Result<Value, Jl2jsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (number, line) = match self.next_line()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+
+        Some(
+            parse_timed(number, &line, &self.options).map_err(|source| Jl2jsError::Parse {
+                line: number,
+                source,
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_records_and_errors() {
+        let input = "{\"a\":1}\n\noops\n[2]\n";
+        let mut reader = JsonlReader::new(input.as_bytes());
+
+        assert_eq!(reader.next().unwrap().unwrap(), json!({"a": 1}));
+        assert!(matches!(
+            reader.next(),
+            Some(Err(Jl2jsError::Parse { line: 3, .. }))
+        ));
+        assert_eq!(reader.next().unwrap().unwrap(), json!([2]));
+        assert!(reader.next().is_none());
+        assert_eq!(reader.line(), 4);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let input = b"1\n{\"a\":\"\xff\xfe\"}\n2\n";
+        let records: Vec<_> = JsonlReader::new(&input[..]).collect();
+
+        assert!(matches!(
+            records.as_slice(),
+            [
+                Ok(_),
+                Err(Jl2jsError::InvalidUtf8 { line: 2, column: 7 }),
+                Ok(_)
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_with_options() {
+        let options = Options {
+            allow_comments: true,
+            max_line_bytes: Some(8),
+            ..Options::default()
+        };
+        let input = "# header\n1\n\"far too long\"\n2";
+
+        let records: Vec<_> = JsonlReader::with_options(input.as_bytes(), &options).collect();
+
+        assert!(matches!(
+            records.as_slice(),
+            [
+                Ok(_),
+                Err(Jl2jsError::LineTooLong { line: 3, max: 8 }),
+                Ok(_)
+            ]
+        ));
+    }
+}
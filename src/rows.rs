@@ -0,0 +1,125 @@
+use crate::reader::DEFAULT_READ_BUFFER_SIZE;
+use crate::{Coerce, Jl2jsError, Options};
+use csv::StringRecord;
+use serde_json::{Map, Value};
+use std::io::Read;
+
+/// Reads CSV with a header row as object records, one per row, with the
+/// header's names as keys. Cells are strings unless
+/// [`Options::csv_infer_types`] is set.
+pub(crate) struct CsvReader<R> {
+    reader: csv::Reader<R>,
+    header: Option<Vec<String>>,
+    infer_types: bool,
+    /// Set once the input can't be read any further.
+    failed: bool,
+}
+
+impl<R: Read> CsvReader<R> {
+    pub(crate) fn new(reader: R, options: &Options) -> Self {
+        Self {
+            reader: csv::ReaderBuilder::new()
+                .buffer_capacity(options.read_buffer_size.unwrap_or(DEFAULT_READ_BUFFER_SIZE))
+                .from_reader(reader),
+            header: None,
+            infer_types: options.csv_infer_types,
+            failed: false,
+        }
+    }
+
+    /// Returns the next row as a record along with the line it starts on.
+    /// A row with more or fewer fields than the header is an error, after
+    /// which reading carries on; a header that can't be read ends it.
+    pub(crate) fn next_record(&mut self) -> Option<Result<(usize, Value), Jl2jsError>> {
+        if self.failed {
+            return None;
+        }
+
+        if self.header.is_none() {
+            match self.reader.headers() {
+                Ok(header) => self.header = Some(header.iter().map(String::from).collect()),
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(csv_error(e)));
+                }
+            }
+        }
+
+        let mut row = StringRecord::new();
+
+        match self.reader.read_record(&mut row) {
+            Ok(false) => None,
+            Ok(true) => {
+                let line = row
+                    .position()
+                    .map_or(0, |position| position.line() as usize);
+                let record: Map<String, Value> = self
+                    .header
+                    .iter()
+                    .flatten()
+                    .zip(&row)
+                    .map(|(key, cell)| (key.clone(), parse_cell(cell, self.infer_types)))
+                    .collect();
+
+                Some(Ok((line, Value::Object(record))))
+            }
+            Err(e) => {
+                self.failed = e.is_io_error();
+                Some(Err(csv_error(e)))
+            }
+        }
+    }
+}
+
+/// The value of a cell: a number or boolean if `infer_types` is set and it
+/// is one exactly as JSON would write it, and a string otherwise. So `007`
+/// and ` 7` stay strings.
+fn parse_cell(cell: &str, infer_types: bool) -> Value {
+    let coerce = Coerce {
+        numbers: infer_types,
+        booleans: infer_types,
+        null: false,
+    };
+
+    coerce
+        .parse(cell)
+        .unwrap_or_else(|| Value::String(cell.to_string()))
+}
+
+fn csv_error(e: csv::Error) -> Jl2jsError {
+    let line = e.position().map_or(0, |position| position.line() as usize);
+    let message = e.to_string();
+
+    match e.into_kind() {
+        csv::ErrorKind::Io(e) => Jl2jsError::Io(e),
+        csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } => Jl2jsError::InvalidCsv {
+            line,
+            message: format!("expected {} fields, found {}", expected_len, len),
+        },
+        csv::ErrorKind::Utf8 { err, .. } => Jl2jsError::InvalidCsv {
+            line,
+            message: format!("field {} is not valid UTF-8", err.field() + 1),
+        },
+        _ => Jl2jsError::InvalidCsv { line, message },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_cell() {
+        assert_eq!(parse_cell("12", false), json!("12"));
+        assert_eq!(parse_cell("12", true), json!(12));
+        assert_eq!(parse_cell("-1.5", true), json!(-1.5));
+        assert_eq!(parse_cell("true", true), json!(true));
+        assert_eq!(parse_cell("007", true), json!("007"));
+        assert_eq!(parse_cell(" 7", true), json!(" 7"));
+        assert_eq!(parse_cell("null", true), json!("null"));
+        assert_eq!(parse_cell("", true), json!(""));
+    }
+}
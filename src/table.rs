@@ -0,0 +1,208 @@
+use crate::writer::{write_compact, CountingWriter, Style};
+use serde_json::{Map, Value};
+use std::io::Write;
+
+/// Writes object records as rows of a CSV table. The header comes from the
+/// keys of the first record, or from every record when `extend_header` is
+/// set, in which case rows are buffered until the input is exhausted.
+///
+/// With `tabs`, the table is TSV instead: cells are separated by tabs and
+/// never quoted, with tabs, newlines, carriage returns and backslashes in
+/// them escaped as `\t`, `\n`, `\r` and `\\`, so each row is one line.
+pub(crate) struct TableWriter<W: Write> {
+    writer: csv::Writer<CountingWriter<W>>,
+    tabs: bool,
+    header: Vec<String>,
+    extend_header: bool,
+    style: Style,
+    buffered: Vec<Map<String, Value>>,
+    records: usize,
+}
+
+impl<W: Write> TableWriter<W> {
+    pub(crate) fn new(writer: W, tabs: bool, extend_header: bool, style: Style) -> Self {
+        let mut builder = csv::WriterBuilder::new();
+
+        if tabs {
+            builder.delimiter(b'\t').quote_style(csv::QuoteStyle::Never);
+        }
+
+        Self {
+            writer: builder.from_writer(CountingWriter::new(writer)),
+            tabs,
+            header: Vec::new(),
+            extend_header,
+            style,
+            buffered: Vec::new(),
+            records: 0,
+        }
+    }
+
+    /// Returns a key of `record` that isn't in the header, when the header
+    /// can no longer change.
+    pub(crate) fn unexpected_key<'r>(&self, record: &'r Map<String, Value>) -> Option<&'r String> {
+        if self.extend_header || self.records == 0 {
+            return None;
+        }
+
+        record.keys().find(|key| !self.header.contains(key))
+    }
+
+    pub(crate) fn write_row(&mut self, record: Map<String, Value>) -> std::io::Result<()> {
+        if self.extend_header {
+            for key in record.keys() {
+                if !self.header.contains(key) {
+                    self.header.push(key.clone());
+                }
+            }
+
+            self.buffered.push(record);
+        } else {
+            if self.records == 0 {
+                self.header = record.keys().cloned().collect();
+                self.write_header()?;
+            }
+
+            self.write_cells(&record)?;
+        }
+
+        self.records += 1;
+
+        Ok(())
+    }
+
+    pub(crate) fn records(&self) -> usize {
+        self.records
+    }
+
+    pub(crate) fn bytes(&self) -> u64 {
+        self.writer.get_ref().count()
+    }
+
+    pub(crate) fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub(crate) fn finish(&mut self) -> std::io::Result<()> {
+        if self.extend_header && !self.buffered.is_empty() {
+            self.write_header()?;
+
+            for record in std::mem::take(&mut self.buffered) {
+                self.write_cells(&record)?;
+            }
+        }
+
+        self.writer.flush()
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let cells: Vec<Vec<u8>> = self
+            .header
+            .iter()
+            .map(|key| self.escape(key.clone().into_bytes()))
+            .collect();
+
+        Ok(self.writer.write_record(cells)?)
+    }
+
+    /// Escapes `cell` as TSV needs it, when writing TSV.
+    fn escape(&self, cell: Vec<u8>) -> Vec<u8> {
+        if !self.tabs
+            || !cell
+                .iter()
+                .any(|b| matches!(b, b'\t' | b'\n' | b'\r' | b'\\'))
+        {
+            return cell;
+        }
+
+        let mut escaped = Vec::with_capacity(cell.len() + 2);
+
+        for byte in cell {
+            match byte {
+                b'\t' => escaped.extend_from_slice(b"\\t"),
+                b'\n' => escaped.extend_from_slice(b"\\n"),
+                b'\r' => escaped.extend_from_slice(b"\\r"),
+                b'\\' => escaped.extend_from_slice(b"\\\\"),
+                byte => escaped.push(byte),
+            }
+        }
+
+        escaped
+    }
+
+    /// Missing keys and nulls become empty cells, strings are written as
+    /// they are, and anything else as its JSON text.
+    fn write_cells(&mut self, record: &Map<String, Value>) -> std::io::Result<()> {
+        let mut cells = Vec::with_capacity(self.header.len());
+
+        for key in &self.header {
+            let cell = match record.get(key) {
+                None | Some(Value::Null) => Vec::new(),
+                Some(Value::String(s)) => s.clone().into_bytes(),
+                Some(value) => {
+                    let mut cell = Vec::new();
+                    write_compact(&mut cell, value, self.style)?;
+                    cell
+                }
+            };
+
+            cells.push(self.escape(cell));
+        }
+
+        Ok(self.writer.write_record(cells)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_extend_header() {
+        let mut output = Vec::new();
+        let mut table = TableWriter::new(&mut output, false, true, Style::default());
+
+        table.write_row(object(json!({"a": 1}))).unwrap();
+        table
+            .write_row(object(json!({"b": "x,y", "a": null})))
+            .unwrap();
+        table.finish().unwrap();
+        drop(table);
+
+        assert_eq!(String::from_utf8(output).unwrap(), "a,b\n1,\n,\"x,y\"\n");
+    }
+
+    #[test]
+    fn test_tsv() {
+        let mut output = Vec::new();
+        let mut table = TableWriter::new(&mut output, true, false, Style::default());
+
+        table
+            .write_row(object(
+                json!({"name": "a, b", "note": "say \"hi\"", "tags": ["x"]}),
+            ))
+            .unwrap();
+        table
+            .write_row(object(
+                json!({"name": "tab\there", "note": "line\nbreak \\ end", "tags": null}),
+            ))
+            .unwrap();
+        table.finish().unwrap();
+        drop(table);
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "name\tnote\ttags\n\
+             a, b\tsay \"hi\"\t[\"x\"]\n\
+             tab\\there\tline\\nbreak \\\\ end\t\n"
+        );
+    }
+}
@@ -0,0 +1,461 @@
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Recursively sorts the keys of every object in `value` by their UTF-8 bytes.
+pub(crate) fn sort_keys(value: &mut Value) {
+    sort_keys_by(value, &|a, b| a.cmp(b));
+}
+
+/// Recursively sorts the keys of every object in `value` by their UTF-16
+/// code units, as RFC 8785 does.
+pub(crate) fn sort_keys_by_utf16(value: &mut Value) {
+    sort_keys_by(value, &|a, b| a.encode_utf16().cmp(b.encode_utf16()));
+}
+
+fn sort_keys_by(value: &mut Value, compare: &dyn Fn(&str, &str) -> Ordering) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = std::mem::take(map).into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| compare(a, b));
+
+            *map = entries
+                .into_iter()
+                .map(|(key, mut value)| {
+                    sort_keys_by(&mut value, compare);
+                    (key, value)
+                })
+                .collect::<Map<String, Value>>();
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| sort_keys_by(value, compare)),
+        _ => {}
+    }
+}
+
+/// Recursively removes object keys whose value is null, including in
+/// objects inside arrays. Null array elements are kept, so the positions of
+/// other elements don't shift.
+pub(crate) fn drop_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(drop_nulls);
+        }
+        Value::Array(values) => values.iter_mut().for_each(drop_nulls),
+        _ => {}
+    }
+}
+
+/// Recursively replaces each CRLF in the strings of `value` with LF. Object
+/// keys are left as they are.
+pub(crate) fn normalize_newlines(value: &mut Value) {
+    match value {
+        Value::String(s) if s.contains("\r\n") => *s = s.replace("\r\n", "\n"),
+        Value::Object(map) => map.values_mut().for_each(normalize_newlines),
+        Value::Array(values) => values.iter_mut().for_each(normalize_newlines),
+        _ => {}
+    }
+}
+
+/// How many arrays and objects deep `value` is nested, counting itself:
+/// 0 for a scalar and 2 for `[{}]`.
+pub(crate) fn depth(value: &Value) -> usize {
+    let children = match value {
+        Value::Array(values) => values.iter().map(depth).max(),
+        Value::Object(map) => map.values().map(depth).max(),
+        _ => return 0,
+    };
+
+    1 + children.unwrap_or(0)
+}
+
+/// The first object key in `value`, at any depth, that has a non-ASCII
+/// character.
+pub(crate) fn non_ascii_key(value: &Value) -> Option<&str> {
+    match value {
+        Value::Array(values) => values.iter().find_map(non_ascii_key),
+        Value::Object(map) => map.iter().find_map(|(key, value)| match key.is_ascii() {
+            true => non_ascii_key(value),
+            false => Some(key.as_str()),
+        }),
+        _ => None,
+    }
+}
+
+/// Keeps only the given top-level keys of an object, in the order they are
+/// listed. Keys missing from the object are ignored.
+pub(crate) fn select(map: &mut Map<String, Value>, keys: &[String]) {
+    let mut selected = Map::new();
+
+    for key in keys {
+        if let Some(value) = map.remove(key) {
+            selected.insert(key.clone(), value);
+        }
+    }
+
+    *map = selected;
+}
+
+/// Removes the key at a dotted `path` such as `user.token`. Each segment
+/// names an object key; arrays are not traversed, so numeric segments are
+/// only ever matched against object keys.
+pub(crate) fn remove_path(map: &mut Map<String, Value>, path: &str) {
+    match path.split_once('.') {
+        Some((key, rest)) => {
+            if let Some(Value::Object(inner)) = map.get_mut(key) {
+                remove_path(inner, rest);
+            }
+        }
+        // `Map::remove` would move the last key into the removed key's slot.
+        None => map.retain(|key, _| key != path),
+    }
+}
+
+/// Renames the key at a dotted `path` such as `user.name` to `to`, keeping
+/// its place in the object. `to` is a key in the same object, not a path.
+/// A key already named `to` is replaced if `overwrite` is set; otherwise
+/// nothing is renamed and `false` is returned. It's not an error for there
+/// to be nothing at `path`.
+pub(crate) fn rename(map: &mut Map<String, Value>, path: &str, to: &str, overwrite: bool) -> bool {
+    if let Some((key, rest)) = path.split_once('.') {
+        return match map.get_mut(key) {
+            Some(Value::Object(inner)) => rename(inner, rest, to, overwrite),
+            _ => true,
+        };
+    }
+
+    if path == to || !map.contains_key(path) {
+        return true;
+    }
+
+    if map.contains_key(to) && !overwrite {
+        return false;
+    }
+
+    *map = std::mem::take(map)
+        .into_iter()
+        .filter(|(key, _)| key != to)
+        .map(|(key, value)| match key == path {
+            true => (to.to_string(), value),
+            false => (key, value),
+        })
+        .collect();
+
+    true
+}
+
+/// Takes the value at a dotted `path` such as `user.address.city` out of
+/// `value`. Segments index into arrays when they're numbers, so `items.0.id`
+/// reaches into the first element of `items`. Returns `None` if there's
+/// nothing at the path.
+pub(crate) fn extract(value: &mut Value, path: &str) -> Option<Value> {
+    let mut current = value;
+
+    for segment in path.split('.') {
+        current = match current {
+            Value::Object(map) => map.get_mut(segment)?,
+            Value::Array(values) => values.get_mut(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    Some(current.take())
+}
+
+/// Inserts each of `fields` into `map`, replacing any value already under
+/// the key unless `keep_existing` is set.
+pub(crate) fn add_fields(
+    map: &mut Map<String, Value>,
+    fields: &[(String, Value)],
+    keep_existing: bool,
+) {
+    for (key, value) in fields {
+        if !(keep_existing && map.contains_key(key)) {
+            map.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Adds the line number a record came from under `key`. Objects get the
+/// key added, replacing any value already under it; anything else is
+/// wrapped as `{key: line, "value": value}`.
+pub(crate) fn with_line_number(value: Value, key: &str, line: usize) -> Value {
+    match value {
+        Value::Object(mut map) => {
+            map.insert(key.to_string(), line.into());
+            Value::Object(map)
+        }
+        value => {
+            let mut map = Map::new();
+            map.insert(key.to_string(), line.into());
+            map.insert("value".to_string(), value);
+            Value::Object(map)
+        }
+    }
+}
+
+/// Wraps `value` as `{key: value}` unless it's already an object.
+pub(crate) fn wrap_scalar(value: Value, key: &str) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(map),
+        value => {
+            let mut map = Map::new();
+            map.insert(key.to_string(), value);
+            Value::Object(map)
+        }
+    }
+}
+
+/// Formats `time` as an RFC 3339 UTC timestamp with millisecond precision,
+/// like `2024-05-01T12:30:00.000Z`.
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, time_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Howard Hinnant's days_from_civil, inverted: days since the epoch to a
+    // proleptic Gregorian date, with years starting in March.
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Flattens nested objects and arrays into a single level of keys joined
+/// by `separator`, so `{"a":{"b":[1]}}` becomes `{"a.b.0":1}`. Empty
+/// objects and arrays are kept as values since they have no leaves.
+pub(crate) fn flatten(map: Map<String, Value>, separator: &str) -> Map<String, Value> {
+    let mut flat = Map::new();
+
+    for (key, value) in map {
+        flatten_into(&mut flat, key, value, separator);
+    }
+
+    flat
+}
+
+fn flatten_into(flat: &mut Map<String, Value>, prefix: String, value: Value, separator: &str) {
+    let children: Vec<(String, Value)> = match value {
+        Value::Object(map) if !map.is_empty() => map.into_iter().collect(),
+        Value::Array(values) if !values.is_empty() => values
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| (index.to_string(), value))
+            .collect(),
+        value => {
+            flat.insert(prefix, value);
+            return;
+        }
+    };
+
+    for (key, value) in children {
+        flatten_into(
+            flat,
+            format!("{}{}{}", prefix, separator, key),
+            value,
+            separator,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sort_keys_nested() {
+        let mut value = json!({"b": [{"d": 1, "c": 2}], "a": {"z": true, "y": null}});
+        sort_keys(&mut value);
+
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"a":{"y":null,"z":true},"b":[{"c":2,"d":1}]}"#
+        );
+    }
+
+    #[test]
+    fn test_drop_nulls() {
+        let mut value = json!({"a": null, "b": {"c": null, "d": 1}, "e": [null, {"f": null}]});
+        drop_nulls(&mut value);
+
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"b":{"d":1},"e":[null,{}]}"#
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        let mut value = json!({"a": 1, "b": 2, "c": 3, "d": 4});
+        let keys = ["c".to_string(), "a".to_string(), "missing".to_string()];
+        select(value.as_object_mut().unwrap(), &keys);
+
+        assert_eq!(serde_json::to_string(&value).unwrap(), r#"{"c":3,"a":1}"#);
+    }
+
+    #[test]
+    fn test_remove_path() {
+        let mut value =
+            json!({"secret": 1, "user": {"token": "t", "name": "n"}, "items": [{"0": 1}]});
+        let map = value.as_object_mut().unwrap();
+
+        for path in ["secret", "user.token", "items.0", "missing.key"] {
+            remove_path(map, path);
+        }
+
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"user":{"name":"n"},"items":[{"0":1}]}"#
+        );
+    }
+
+    #[test]
+    fn test_extract() {
+        let value = json!({"user": {"address": {"city": "Oslo"}}, "items": [{"id": 7}]});
+        let extracted = |path| extract(&mut value.clone(), path);
+
+        assert_eq!(extracted("user.address.city"), Some(json!("Oslo")));
+        assert_eq!(extracted("items.0.id"), Some(json!(7)));
+        assert_eq!(
+            extracted("user"),
+            Some(json!({"address": {"city": "Oslo"}}))
+        );
+        assert_eq!(extracted("user.phone"), None);
+        assert_eq!(extracted("items.1.id"), None);
+        assert_eq!(extracted("items.first"), None);
+        assert_eq!(extracted("user.address.city.name"), None);
+    }
+
+    #[test]
+    fn test_add_fields() {
+        let mut value = json!({"source": "app", "id": 1});
+        let fields = [
+            ("source".to_string(), json!("import")),
+            ("batch".to_string(), json!(7)),
+        ];
+
+        add_fields(value.as_object_mut().unwrap(), &fields, true);
+        assert_eq!(value, json!({"source": "app", "id": 1, "batch": 7}));
+
+        add_fields(value.as_object_mut().unwrap(), &fields, false);
+        assert_eq!(value, json!({"source": "import", "id": 1, "batch": 7}));
+    }
+
+    #[test]
+    fn test_with_line_number() {
+        assert_eq!(
+            with_line_number(json!({"a": 1}), "line", 3),
+            json!({"a": 1, "line": 3})
+        );
+        assert_eq!(
+            with_line_number(json!([1]), "n", 4),
+            json!({"n": 4, "value": [1]})
+        );
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        use std::time::Duration;
+
+        let at = |millis| rfc3339(UNIX_EPOCH + Duration::from_millis(millis));
+
+        assert_eq!(at(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(at(951_782_400_123), "2000-02-29T00:00:00.123Z");
+        assert_eq!(at(1_735_689_599_999), "2024-12-31T23:59:59.999Z");
+    }
+
+    #[test]
+    fn test_flatten() {
+        let value = json!({"a": {"b": {"c": 1}, "d": [true, {"e": null}]}, "f": {}, "g": "h"});
+        let Value::Object(map) = value else {
+            unreachable!()
+        };
+
+        assert_eq!(
+            serde_json::to_string(&flatten(map, "_")).unwrap(),
+            r#"{"a_b_c":1,"a_d_0":true,"a_d_1_e":null,"f":{},"g":"h"}"#
+        );
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut value = json!({"a": 1, "b": 2, "user": {"name": "n", "login": "l"}});
+        let map = value.as_object_mut().unwrap();
+
+        assert!(rename(map, "a", "z", false));
+        assert!(rename(map, "missing", "y", false));
+        assert!(!rename(map, "user.name", "login", false));
+        assert_eq!(
+            value,
+            json!({"z": 1, "b": 2, "user": {"name": "n", "login": "l"}})
+        );
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"z":1,"b":2,"user":{"name":"n","login":"l"}}"#
+        );
+
+        let map = value.as_object_mut().unwrap();
+        assert!(rename(map, "user.name", "login", true));
+        assert_eq!(value, json!({"z": 1, "b": 2, "user": {"login": "n"}}));
+    }
+
+    #[test]
+    fn test_depth() {
+        assert_eq!(depth(&json!(1)), 0);
+        assert_eq!(depth(&json!([])), 1);
+        assert_eq!(depth(&json!([{}])), 2);
+        assert_eq!(depth(&json!({"a": 1, "b": {"c": [1, [2]]}, "d": []})), 4);
+    }
+
+    #[test]
+    fn test_non_ascii_key() {
+        assert_eq!(non_ascii_key(&json!({"a": {"b": "café"}})), None);
+        assert_eq!(
+            non_ascii_key(&json!({"a": [1, {"b": 1, "né": 2}], "ü": 3})),
+            Some("né")
+        );
+    }
+
+    #[test]
+    fn test_normalize_newlines() {
+        let mut value =
+            json!({"a": "one\r\ntwo\r\n", "b\r\n": ["x\ry", {"c": "\r\n\r\n"}], "d": 1});
+        normalize_newlines(&mut value);
+
+        assert_eq!(
+            value,
+            json!({"a": "one\ntwo\n", "b\r\n": ["x\ry", {"c": "\n\n"}], "d": 1})
+        );
+    }
+
+    #[test]
+    fn test_wrap_scalar() {
+        assert_eq!(wrap_scalar(json!({"a": 1}), "v"), json!({"a": 1}));
+        assert_eq!(wrap_scalar(json!(1), "v"), json!({"v": 1}));
+        assert_eq!(wrap_scalar(json!([null]), "v"), json!({"v": [null]}));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{matches_input, open_input, with_path};
+use glob::Pattern;
+use jl2js::{convert_all, Jl2jsError, Options, OutputSink};
+use notify::event::{AccessKind, AccessMode, CreateKind, ModifyKind};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Whether the platform reports files being closed after writing. Where it
+/// doesn't, a file is taken to be complete once it's gone [`QUIET`] without
+/// changing.
+const CLOSE_EVENTS: bool = cfg!(any(target_os = "linux", target_os = "android"));
+
+const QUIET: Duration = Duration::from_secs(1);
+
+/// Converts each JSONL file that appears in `dir` to a `.json` file beside
+/// it, until interrupted. Files already there are left alone. A file is
+/// converted once whatever wrote it closes it, or is moved into `dir`
+/// complete. Failing to convert one is reported and doesn't stop watching.
+pub fn watch(dir: &Path, pattern: Option<&Pattern>, options: &Options) -> Result<(), Jl2jsError> {
+    let (sender, events) = mpsc::channel();
+    let notify_error =
+        |e: notify::Error| std::io::Error::other(format!("{}: {}", dir.display(), e));
+
+    let mut watcher = notify::recommended_watcher(sender).map_err(notify_error)?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .map_err(notify_error)?;
+
+    eprintln!(
+        "jl2js: watching {} for new files; stop with Ctrl-C",
+        dir.display()
+    );
+
+    // Files that have changed but may still be being written, and when.
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+
+    loop {
+        match events.recv_timeout(QUIET) {
+            Ok(event) => {
+                let event = event.map_err(notify_error)?;
+
+                for path in event.paths {
+                    if !matches_input(&path, pattern) || is_output(&path) {
+                        continue;
+                    }
+
+                    match event.kind {
+                        EventKind::Access(AccessKind::Close(AccessMode::Write))
+                        | EventKind::Modify(ModifyKind::Name(_)) => {
+                            pending.remove(&path);
+
+                            if path.is_file() {
+                                convert_file(&path, options);
+                            }
+                        }
+                        EventKind::Create(CreateKind::File | CreateKind::Any)
+                        | EventKind::Modify(_)
+                            if !CLOSE_EVENTS =>
+                        {
+                            pending.insert(path, Instant::now());
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        let quiet: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= QUIET)
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        for path in quiet {
+            pending.remove(&path);
+
+            if path.is_file() {
+                convert_file(&path, options);
+            }
+        }
+    }
+}
+
+/// Whether `path` is, or could be, written by [`convert_file`]: a file that
+/// would be converted to itself, or a temporary file on its way to
+/// replacing one. Converting these, as a pattern like `*` would, never ends.
+fn is_output(path: &Path) -> bool {
+    path.with_extension("json") == path
+        || path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(".jl2js-tmp"))
+}
+
+/// Converts `path` to a `.json` file beside it, reporting any failure.
+fn convert_file(path: &Path, options: &Options) {
+    let output = path.with_extension("json");
+
+    let result = (|| {
+        let source = open_input(Some(path.to_path_buf()), false)?;
+        let mut sink = OutputSink::from_file(output.clone()).map_err(|e| with_path(e, &output))?;
+
+        let stats = convert_all([Ok(source)], &mut sink, options)?;
+        sink.finish().map_err(|e| with_path(e, &output))?;
+
+        Ok::<_, Jl2jsError>(stats)
+    })();
+
+    match result {
+        Ok(stats) => {
+            log::info!(
+                "converted {} to {}: {} records, {} skipped",
+                path.display(),
+                output.display(),
+                stats.records,
+                stats.skipped
+            );
+        }
+        // I/O errors already name the file.
+        Err(Jl2jsError::Io(e)) => eprintln!("jl2js: {}", e),
+        Err(e) => eprintln!("jl2js: {}: {}", path.display(), e),
+    }
+}
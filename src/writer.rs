@@ -0,0 +1,857 @@
+use crate::error::serialize_error;
+use crate::{Jl2jsError, Options};
+use serde::Serialize;
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter};
+use serde_json::value::RawValue;
+use serde_json::{Serializer, Value};
+use std::io::Write;
+use std::str::FromStr;
+
+/// How records are framed in the output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Format {
+    /// A single JSON array containing every record.
+    #[default]
+    Array,
+    /// One record per line with no enclosing array (newline-delimited JSON).
+    /// Pretty printed records span several lines and are each followed by
+    /// a single newline.
+    Ndjson,
+    /// An RFC 7464 `application/json-seq` stream: each record is prefixed
+    /// with the record separator byte (0x1E) and followed by a newline.
+    JsonSeq,
+    /// A CSV table with a header row. Only object records can be written;
+    /// nested values are written as embedded JSON.
+    Csv,
+    /// Like [`Format::Csv`], but tab separated. Cells are never quoted, so
+    /// commas and quotes are written as they are; tabs, newlines, carriage
+    /// returns and backslashes in them are escaped as `\t`, `\n`, `\r` and
+    /// `\\`.
+    Tsv,
+    /// A YAML sequence with each record as an item, written as the records
+    /// arrive. Only the float and ASCII options of JSON output don't apply.
+    Yaml,
+    /// A MessagePack array of the records, or a map for output keyed by a
+    /// field. The array's length comes first, so the records are held in
+    /// memory, already encoded, until the input is exhausted.
+    Msgpack,
+}
+
+/// What encloses the records of [`Format::Array`] output, or the object of
+/// output keyed by a field.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum Wrap {
+    /// Nothing: records are only separated by commas.
+    None,
+    /// The brackets of an array, or braces of an object.
+    #[default]
+    Array,
+    /// An array held by this field of an object, as in `{"records":[...]}`.
+    Field(String),
+}
+
+impl FromStr for Wrap {
+    type Err = String;
+
+    /// Parses `none`, `array` or `field:NAME`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "none" => Ok(Wrap::None),
+            None if s == "array" => Ok(Wrap::Array),
+            Some(("field", name)) if !name.is_empty() => Ok(Wrap::Field(name.to_string())),
+            _ => Err(format!("expected none, array or field:NAME, got {:?}", s)),
+        }
+    }
+}
+
+/// How floating point numbers are written. Numbers written without a
+/// fraction or exponent, like `1`, are integers and always written as they
+/// are; only ones like `1.0` or `1e3` are floats.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// The fewest digits that read back as the same number, as in `1.0`
+    /// or `0.1`.
+    #[default]
+    Shortest,
+    /// Exactly this many digits after the decimal point, rounding if need
+    /// be, as in `1.50` for `fixed:2`. With none, floats look like integers.
+    Fixed(usize),
+}
+
+impl FromStr for FloatFormat {
+    type Err = String;
+
+    /// Parses `shortest` or `fixed:N`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "shortest" => Ok(FloatFormat::Shortest),
+            Some(("fixed", digits)) => digits
+                .parse()
+                .map(FloatFormat::Fixed)
+                .map_err(|e| format!("invalid number of digits {:?}: {}", digits, e)),
+            _ => Err(format!("expected shortest or fixed:N, got {:?}", s)),
+        }
+    }
+}
+
+/// How the scalars in records are written.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Style {
+    pub(crate) float_format: FloatFormat,
+    /// Escape every character outside ASCII in strings as `\uXXXX`, or a
+    /// surrogate pair of them.
+    pub(crate) ascii: bool,
+    /// Write every number as RFC 8785 requires, instead of as
+    /// `float_format` says.
+    pub(crate) canonical: bool,
+}
+
+impl Style {
+    pub(crate) fn new(options: &Options) -> Self {
+        Self {
+            float_format: options.float_format,
+            ascii: options.ascii && !options.canonical,
+            canonical: options.canonical,
+        }
+    }
+}
+
+/// Writes `value` as ECMAScript's `Number.prototype.toString` does, which
+/// RFC 8785 uses for every number: the fewest digits that read back as
+/// `value`, as an integer or decimal unless its exponent is at least 21 or
+/// below -6. `value` has to be finite, as any in JSON is.
+fn write_canonical<W: ?Sized + Write>(writer: &mut W, value: f64) -> std::io::Result<()> {
+    if value == 0.0 {
+        return writer.write_all(b"0");
+    }
+
+    if value < 0.0 {
+        writer.write_all(b"-")?;
+    }
+
+    // Rust writes the shortest digits that read back the same, as in
+    // `1.2345e-7`.
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits = mantissa.replace('.', "");
+    let exponent: i64 = exponent.parse().unwrap_or_default();
+
+    // The decimal point goes after `point` digits.
+    let point = exponent + 1;
+    let len = digits.len() as i64;
+
+    match point {
+        _ if len <= point && point <= 21 => {
+            write!(writer, "{}{}", digits, "0".repeat((point - len) as usize))
+        }
+        1..=21 => {
+            let (whole, fraction) = digits.split_at(point as usize);
+            write!(writer, "{}.{}", whole, fraction)
+        }
+        -5..=0 => write!(writer, "0.{}{}", "0".repeat(-point as usize), digits),
+        _ => {
+            let sign = if exponent < 0 { '-' } else { '+' };
+            write!(writer, "{}e{}{}", mantissa, sign, exponent.abs())
+        }
+    }
+}
+
+/// Writes scalars in `style` and everything else as `inner` would.
+struct StyledFormatter<F> {
+    inner: F,
+    style: Style,
+}
+
+impl<F: Formatter> Formatter for StyledFormatter<F> {
+    fn write_string_fragment<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        fragment: &str,
+    ) -> std::io::Result<()> {
+        if !self.style.ascii {
+            return self.inner.write_string_fragment(writer, fragment);
+        }
+
+        let mut ascii_from = 0;
+
+        for (i, c) in fragment.char_indices() {
+            if c.is_ascii() {
+                continue;
+            }
+
+            writer.write_all(&fragment.as_bytes()[ascii_from..i])?;
+
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                write!(writer, "\\u{:04x}", unit)?;
+            }
+
+            ascii_from = i + c.len_utf8();
+        }
+
+        writer.write_all(&fragment.as_bytes()[ascii_from..])
+    }
+
+    fn write_f32<W: ?Sized + Write>(&mut self, writer: &mut W, value: f32) -> std::io::Result<()> {
+        match self.style.float_format {
+            FloatFormat::Shortest => self.inner.write_f32(writer, value),
+            FloatFormat::Fixed(digits) => write!(writer, "{:.*}", digits, value),
+        }
+    }
+
+    fn write_f64<W: ?Sized + Write>(&mut self, writer: &mut W, value: f64) -> std::io::Result<()> {
+        if self.style.canonical {
+            return write_canonical(writer, value);
+        }
+
+        match self.style.float_format {
+            FloatFormat::Shortest => self.inner.write_f64(writer, value),
+            FloatFormat::Fixed(digits) => write!(writer, "{:.*}", digits, value),
+        }
+    }
+
+    fn write_i64<W: ?Sized + Write>(&mut self, writer: &mut W, value: i64) -> std::io::Result<()> {
+        match self.style.canonical {
+            true => write_canonical(writer, value as f64),
+            false => self.inner.write_i64(writer, value),
+        }
+    }
+
+    fn write_u64<W: ?Sized + Write>(&mut self, writer: &mut W, value: u64) -> std::io::Result<()> {
+        match self.style.canonical {
+            true => write_canonical(writer, value as f64),
+            false => self.inner.write_u64(writer, value),
+        }
+    }
+
+    /// Numbers are kept as their text with the `arbitrary-precision`
+    /// feature, so floats are told apart by how they're written.
+    fn write_number_str<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        value: &str,
+    ) -> std::io::Result<()> {
+        let float = value.contains(['.', 'e', 'E']);
+
+        match (self.style.float_format, value.parse::<f64>()) {
+            (_, Ok(number)) if self.style.canonical => write_canonical(writer, number),
+            (FloatFormat::Fixed(digits), Ok(number)) if float => {
+                write!(writer, "{:.*}", digits, number)
+            }
+            _ => self.inner.write_number_str(writer, value),
+        }
+    }
+
+    fn begin_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.begin_array(writer)
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.end_array(writer)
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        self.inner.begin_array_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.end_array_value(writer)
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.begin_object(writer)
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.end_object(writer)
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        self.inner.begin_object_key(writer, first)
+    }
+
+    fn end_object_key<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.end_object_key(writer)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.inner.end_object_value(writer)
+    }
+}
+
+/// Writes arrays compactly but for a space after each comma, as in
+/// `[1, 2, 3]`.
+struct InlineFormatter;
+
+impl Formatter for InlineFormatter {
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+}
+
+/// Serializes `value` with each array that holds only scalars, or nothing,
+/// written on one line as in `[1, 2, 3]`, whatever the formatter.
+struct InlineScalarArrays<'a> {
+    value: &'a Value,
+    style: Style,
+}
+
+impl InlineScalarArrays<'_> {
+    fn with<'v>(&self, value: &'v Value) -> InlineScalarArrays<'v> {
+        InlineScalarArrays {
+            value,
+            style: self.style,
+        }
+    }
+}
+
+impl Serialize for InlineScalarArrays<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        match self.value {
+            Value::Array(items)
+                if items
+                    .iter()
+                    .all(|item| !item.is_array() && !item.is_object()) =>
+            {
+                let mut json = Vec::new();
+                serialize_with(&mut json, self.value, InlineFormatter, self.style)
+                    .map_err(S::Error::custom)?;
+
+                // serde_json only writes valid UTF-8.
+                let json = String::from_utf8(json).map_err(S::Error::custom)?;
+                RawValue::from_string(json)
+                    .map_err(S::Error::custom)?
+                    .serialize(serializer)
+            }
+            Value::Array(items) => serializer.collect_seq(items.iter().map(|item| self.with(item))),
+            Value::Object(map) => {
+                serializer.collect_map(map.iter().map(|(key, value)| (key, self.with(value))))
+            }
+            value => value.serialize(serializer),
+        }
+    }
+}
+
+/// Writes `value` compactly to `writer`, with scalars in `style`.
+pub(crate) fn write_compact<W: Write, T: Serialize + ?Sized>(
+    writer: W,
+    value: &T,
+    style: Style,
+) -> serde_json::Result<()> {
+    serialize_with(writer, value, CompactFormatter, style)
+}
+
+fn serialize_with<W: Write, F: Formatter, T: Serialize + ?Sized>(
+    writer: W,
+    value: &T,
+    inner: F,
+    style: Style,
+) -> serde_json::Result<()> {
+    let formatter = StyledFormatter { inner, style };
+    value.serialize(&mut Serializer::with_formatter(writer, formatter))
+}
+
+/// A JSON array that output is appended to. Its opening bracket, and any
+/// records it holds, have already been written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExistingArray {
+    Empty,
+    NonEmpty,
+}
+
+const DEFAULT_INDENT: &str = "  ";
+
+/// Writes records to the output, taking care of the framing around and
+/// between them.
+pub(crate) struct RecordWriter<W: Write> {
+    writer: CountingWriter<W>,
+    format: Format,
+    pretty: bool,
+    /// Nesting depth below which pretty records are written compactly.
+    pretty_depth: Option<usize>,
+    /// When pretty printing, arrays of scalars are kept on one line.
+    compact_scalar_arrays: bool,
+    indent: Vec<u8>,
+    style: Style,
+    /// Records are written as the values of a single object rather than
+    /// as array elements.
+    keyed: bool,
+    wrap: Wrap,
+    /// Unenclosed records end with a newline, rather than only being
+    /// separated by them.
+    final_newline: bool,
+    /// The opening bracket has already been written.
+    appending: bool,
+    /// Only open the enclosing value once there's a record to write, or
+    /// the output is finished.
+    defer_open: bool,
+    opened: bool,
+    /// Records are only written while they fit in this many bytes along
+    /// with what closes the output.
+    max_bytes: Option<u64>,
+    /// A record didn't fit, so no more are written.
+    full: bool,
+    first: bool,
+    records: usize,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub(crate) fn new(writer: W, options: &Options) -> Self {
+        Self {
+            writer: CountingWriter::new(writer),
+            format: options.format,
+            pretty: options.pretty && !options.canonical,
+            pretty_depth: options.pretty_depth,
+            compact_scalar_arrays: options.compact_scalar_arrays,
+            indent: options.indent.as_deref().unwrap_or(DEFAULT_INDENT).into(),
+            style: Style::new(options),
+            keyed: options.key_by.is_some()
+                || options.group_by.is_some()
+                || options.merge.is_some(),
+            wrap: options.wrap.clone(),
+            // RFC 7464 requires every record to end with a newline.
+            final_newline: !options.omit_final_newline || options.format == Format::JsonSeq,
+            appending: options.append.is_some(),
+            defer_open: options.defer_open,
+            opened: false,
+            max_bytes: options.max_output_bytes,
+            full: false,
+            first: options.append != Some(ExistingArray::NonEmpty),
+            records: 0,
+        }
+    }
+
+    /// Whether the records are wrapped in a single enclosing value.
+    fn enclosed(&self) -> bool {
+        self.keyed || self.format == Format::Array
+    }
+
+    pub(crate) fn begin(&mut self) -> std::io::Result<()> {
+        match self.defer_open {
+            true => Ok(()),
+            false => self.open(),
+        }
+    }
+
+    /// Writes what comes before the first record, once.
+    fn open(&mut self) -> std::io::Result<()> {
+        if self.opened {
+            return Ok(());
+        }
+
+        self.opened = true;
+
+        if !self.enclosed() || self.appending || self.wrap == Wrap::None {
+            return Ok(());
+        }
+
+        if let Wrap::Field(name) = &self.wrap {
+            self.writer.write_all(b"{")?;
+            write_compact(&mut self.writer, name, self.style)?;
+            self.writer
+                .write_all(if self.pretty { b": " } else { b":" })?;
+        }
+
+        self.writer
+            .write_all(if self.keyed { b"{" } else { b"[" })?;
+
+        if self.pretty {
+            self.writer.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn write_record(&mut self, value: &Value) -> Result<(), Jl2jsError> {
+        self.capped(|writer| {
+            writer.start_record()?;
+            writer.serialize(value)?;
+
+            Ok(writer.end_record()?)
+        })
+    }
+
+    /// Writes `json`, which must already be known to hold a single valid
+    /// JSON value, as a record exactly as it is.
+    pub(crate) fn write_raw(&mut self, json: &str) -> Result<(), Jl2jsError> {
+        self.capped(|writer| {
+            writer.start_record()?;
+            writer.writer.write_all(json.as_bytes())?;
+
+            Ok(writer.end_record()?)
+        })
+    }
+
+    /// Whether a record has been left out for not fitting in `max_bytes`.
+    pub(crate) fn full(&self) -> bool {
+        self.full
+    }
+
+    /// Writes a record with `write`, unless the output would no longer fit
+    /// in `max_bytes` once closed, in which case nothing is written and the
+    /// output is full.
+    fn capped<F>(&mut self, write: F) -> Result<(), Jl2jsError>
+    where
+        F: FnOnce(&mut Self) -> Result<(), Jl2jsError>,
+    {
+        let Some(max_bytes) = self.max_bytes else {
+            return write(self);
+        };
+
+        if self.full {
+            return Ok(());
+        }
+
+        let (first, opened, records) = (self.first, self.opened, self.records);
+
+        self.writer.hold();
+        let result = write(self);
+        let fits = self.writer.count() + self.closing().len() as u64 <= max_bytes;
+        self.writer.release(result.is_ok() && fits)?;
+
+        if result.is_err() || !fits {
+            (self.first, self.opened, self.records) = (first, opened, records);
+            self.full = result.is_ok();
+        }
+
+        result
+    }
+
+    fn start_record(&mut self) -> std::io::Result<()> {
+        self.separate()?;
+
+        if !self.keyed && self.format == Format::JsonSeq {
+            self.writer.write_all(b"\x1e")?;
+        }
+
+        Ok(())
+    }
+
+    fn end_record(&mut self) -> std::io::Result<()> {
+        if !self.enclosed() && self.final_newline {
+            self.writer.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a record under `key`, for output keyed by a field.
+    pub(crate) fn write_entry(&mut self, key: &str, value: &Value) -> Result<(), Jl2jsError> {
+        self.capped(|writer| {
+            writer.separate()?;
+
+            write_compact(&mut writer.writer, key, writer.style).map_err(serialize_error)?;
+            writer
+                .writer
+                .write_all(if writer.pretty { b": " } else { b":" })?;
+            writer.serialize(value)
+        })
+    }
+
+    fn separate(&mut self) -> std::io::Result<()> {
+        self.open()?;
+
+        if self.enclosed() && !self.first {
+            self.writer.write_all(b",")?;
+
+            if self.pretty {
+                self.writer.write_all(b"\n")?;
+            }
+        } else if !self.final_newline && !self.first {
+            self.writer.write_all(b"\n")?;
+        }
+
+        self.first = false;
+        self.records += 1;
+
+        Ok(())
+    }
+
+    fn serialize(&mut self, value: &Value) -> Result<(), Jl2jsError> {
+        let result = if self.pretty && self.compact_scalar_arrays {
+            self.serialize_pretty(&InlineScalarArrays {
+                value,
+                style: self.style,
+            })
+        } else if self.pretty {
+            self.serialize_pretty(value)
+        } else {
+            write_compact(&mut self.writer, value, self.style)
+        };
+
+        result.map_err(serialize_error)
+    }
+
+    fn serialize_pretty<T: Serialize + ?Sized>(&mut self, value: &T) -> serde_json::Result<()> {
+        let style = self.style;
+
+        match self.pretty_depth {
+            Some(max_depth) => {
+                let formatter = DepthFormatter::new(&self.indent, max_depth);
+                serialize_with(&mut self.writer, value, formatter, style)
+            }
+            None => {
+                let formatter = PrettyFormatter::with_indent(&self.indent);
+                serialize_with(&mut self.writer, value, formatter, style)
+            }
+        }
+    }
+
+    pub(crate) fn records(&self) -> usize {
+        self.records
+    }
+
+    pub(crate) fn bytes(&self) -> u64 {
+        self.writer.count()
+    }
+
+    pub(crate) fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub(crate) fn finish(&mut self) -> std::io::Result<()> {
+        self.open()?;
+
+        let closing = self.closing();
+        self.writer.write_all(&closing)?;
+        self.writer.flush()
+    }
+
+    /// What's written after the last record to close the output.
+    fn closing(&self) -> Vec<u8> {
+        let mut closing = Vec::new();
+
+        if self.enclosed() && self.wrap != Wrap::None {
+            if self.pretty {
+                closing.push(b'\n');
+            }
+
+            closing.push(if self.keyed { b'}' } else { b']' });
+
+            if let Wrap::Field(_) = self.wrap {
+                closing.push(b'}');
+            }
+        }
+
+        closing
+    }
+}
+
+/// Pretty prints like [`PrettyFormatter`], but only objects and arrays
+/// nested at most `max_depth` deep; those inside them are written compactly.
+/// A record itself is at depth 1.
+struct DepthFormatter<'a> {
+    indent: &'a [u8],
+    max_depth: usize,
+    /// Objects and arrays currently open.
+    depth: usize,
+    /// The innermost open object or array has had a value written to it.
+    has_value: bool,
+}
+
+impl<'a> DepthFormatter<'a> {
+    fn new(indent: &'a [u8], max_depth: usize) -> Self {
+        Self {
+            indent,
+            max_depth,
+            depth: 0,
+            has_value: false,
+        }
+    }
+
+    /// Whether the innermost open object or array is pretty printed.
+    fn expanded(&self) -> bool {
+        self.depth <= self.max_depth
+    }
+
+    fn begin<W: ?Sized + Write>(&mut self, writer: &mut W, bracket: &[u8]) -> std::io::Result<()> {
+        self.depth += 1;
+        self.has_value = false;
+        writer.write_all(bracket)
+    }
+
+    fn end<W: ?Sized + Write>(&mut self, writer: &mut W, bracket: &[u8]) -> std::io::Result<()> {
+        if self.expanded() && self.has_value {
+            writer.write_all(b"\n")?;
+            self.write_indent(writer, self.depth - 1)?;
+        }
+
+        self.depth -= 1;
+        writer.write_all(bracket)
+    }
+
+    fn begin_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        if !self.expanded() {
+            return if first {
+                Ok(())
+            } else {
+                writer.write_all(b",")
+            };
+        }
+
+        writer.write_all(if first { b"\n" } else { b",\n" })?;
+        self.write_indent(writer, self.depth)
+    }
+
+    fn write_indent<W: ?Sized + Write>(&self, writer: &mut W, depth: usize) -> std::io::Result<()> {
+        for _ in 0..depth {
+            writer.write_all(self.indent)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Formatter for DepthFormatter<'_> {
+    fn begin_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.begin(writer, b"[")
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.end(writer, b"]")
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        self.begin_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, _writer: &mut W) -> std::io::Result<()> {
+        self.has_value = true;
+        Ok(())
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.begin(writer, b"{")
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.end(writer, b"}")
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        self.begin_value(writer, first)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(if self.expanded() { b": " } else { b":" })
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, _writer: &mut W) -> std::io::Result<()> {
+        self.has_value = true;
+        Ok(())
+    }
+}
+
+/// Serializes a [`Value`] with its numbers as the serializer's own, for
+/// formats other than JSON. With the `arbitrary-precision` feature,
+/// serde_json would otherwise write them as maps holding their text;
+/// numbers that don't fit in 64 bits are rounded.
+pub(crate) struct NativeNumbers<'a>(pub(crate) &'a Value);
+
+impl Serialize for NativeNumbers<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Number(n) => match (n.as_u64(), n.as_i64(), n.as_f64()) {
+                (Some(u), _, _) => serializer.serialize_u64(u),
+                (_, Some(i), _) => serializer.serialize_i64(i),
+                (_, _, Some(f)) => serializer.serialize_f64(f),
+                _ => serializer.serialize_str(&n.to_string()),
+            },
+            Value::Array(values) => serializer.collect_seq(values.iter().map(NativeNumbers)),
+            Value::Object(map) => {
+                serializer.collect_map(map.iter().map(|(key, value)| (key, NativeNumbers(value))))
+            }
+            value => value.serialize(serializer),
+        }
+    }
+}
+
+/// Counts the bytes written through to the inner writer.
+pub(crate) struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+    /// Bytes held back since [`CountingWriter::hold`], until released.
+    held: Option<Vec<u8>>,
+}
+
+impl<W> CountingWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            count: 0,
+            held: None,
+        }
+    }
+
+    /// Bytes written, counting any held back.
+    pub(crate) fn count(&self) -> u64 {
+        self.count + self.held.as_ref().map_or(0, |held| held.len() as u64)
+    }
+
+    /// Holds back what's written from now on, until it's released.
+    pub(crate) fn hold(&mut self) {
+        self.held = Some(Vec::new());
+    }
+}
+
+impl<W: Write> CountingWriter<W> {
+    /// Writes what was held back, if `keep` is set, or drops it.
+    pub(crate) fn release(&mut self, keep: bool) -> std::io::Result<()> {
+        match self.held.take() {
+            Some(held) if keep => self.write_all(&held),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(held) = &mut self.held {
+            held.extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,60 @@
+use crate::writer::{CountingWriter, NativeNumbers};
+use crate::Jl2jsError;
+use serde_json::Value;
+use std::io::Write;
+
+/// Writes records as the items of a YAML sequence. Each one is written as
+/// soon as it's given, so nothing is buffered; no records at all is
+/// written as `[]`.
+pub(crate) struct YamlWriter<W: Write> {
+    writer: CountingWriter<W>,
+    records: usize,
+}
+
+impl<W: Write> YamlWriter<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            writer: CountingWriter::new(writer),
+            records: 0,
+        }
+    }
+
+    pub(crate) fn write_record(&mut self, value: &Value) -> Result<(), Jl2jsError> {
+        // A sequence of one, written as `- ...`, so that the items written
+        // one after another make up a single sequence.
+        serde_yaml::to_writer(&mut self.writer, &[NativeNumbers(value)]).map_err(yaml_error)?;
+        self.records += 1;
+
+        Ok(())
+    }
+
+    pub(crate) fn records(&self) -> usize {
+        self.records
+    }
+
+    pub(crate) fn bytes(&self) -> u64 {
+        self.writer.count()
+    }
+
+    pub(crate) fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub(crate) fn finish(&mut self) -> std::io::Result<()> {
+        if self.records == 0 {
+            self.writer.write_all(b"[]\n")?;
+        }
+
+        self.writer.flush()
+    }
+}
+
+/// Any record can be written as YAML, so failing to is failing to write,
+/// keeping the kind of any I/O error behind it.
+fn yaml_error(e: serde_yaml::Error) -> Jl2jsError {
+    let kind = std::error::Error::source(&e)
+        .and_then(|source| source.downcast_ref::<std::io::Error>())
+        .map_or(std::io::ErrorKind::Other, std::io::Error::kind);
+
+    Jl2jsError::Io(std::io::Error::new(kind, e))
+}
@@ -0,0 +1,356 @@
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+fn jl2js(args: &[&str]) -> Option<i32> {
+    Command::new(env!("CARGO_BIN_EXE_jl2js"))
+        .args(args)
+        .output()
+        .unwrap()
+        .status
+        .code()
+}
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("jl2js-{}-{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_exit_codes() {
+    let good = temp_file("good.jsonl", "{\"a\":1}\n");
+    let bad = temp_file("bad.jsonl", "{\"a\":1}\noops\n");
+    let corrupt = temp_file("corrupt.jsonl.gz", "not gzip\n");
+    let schema = temp_file("schema.json", "{\"type\":");
+    let [good_path, bad_path, corrupt_path, schema_path] =
+        [&good, &bad, &corrupt, &schema].map(|path| path.to_str().unwrap());
+
+    assert_eq!(jl2js(&["--input", good_path]), Some(0));
+    assert_eq!(jl2js(&["--input", bad_path]), Some(1));
+    assert_eq!(jl2js(&["--input", "/nonexistent/input.jsonl"]), Some(2));
+    // A broken gzip file is an I/O error, however the decompressor reports it.
+    assert_eq!(jl2js(&["--input", corrupt_path]), Some(2));
+    assert_eq!(jl2js(&["--no-such-flag"]), Some(3));
+    assert_eq!(
+        jl2js(&["--input", good_path, "--input", good_path, "--follow"]),
+        Some(3)
+    );
+    assert_eq!(
+        jl2js(&["--input", good_path, "--schema", schema_path]),
+        Some(3)
+    );
+    assert_eq!(jl2js(&["--help"]), Some(0));
+
+    for path in [good, bad, corrupt, schema] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_continue_on_io_error() {
+    let good = temp_file("continue.jsonl", "{\"a\":1}\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_jl2js"))
+        .args([
+            "--continue-on-io-error",
+            "--input",
+            "/nonexistent/input.jsonl",
+        ])
+        .arg("--input")
+        .arg(&good)
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(output.stdout, b"[{\"a\":1}]");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("/nonexistent/input.jsonl"));
+
+    fs::remove_file(good).unwrap();
+}
+
+#[test]
+fn test_watch() {
+    let dir = std::env::temp_dir().join(format!("jl2js-{}-watch", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jl2js"))
+        .arg("--watch")
+        .arg(&dir)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Wait until the directory is being watched.
+    let mut line = String::new();
+    BufReader::new(child.stderr.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    assert!(line.contains("watching"), "{}", line);
+
+    fs::write(dir.join("events.jsonl"), "{\"a\":1}\n{\"a\":2}\n").unwrap();
+
+    let output = dir.join("events.json");
+    let started = Instant::now();
+
+    while !output.exists() && started.elapsed() < Duration::from_secs(10) {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&output).unwrap(),
+        "[{\"a\":1},{\"a\":2}]"
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_warn_duplicate_keys() {
+    let input = temp_file("duplicates.jsonl", "{\"a\":1}\n{\"a\":1,\"a\":2}\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_jl2js"))
+        .arg("--warn-duplicate-keys")
+        .arg("--input")
+        .arg(&input)
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"[{\"a\":1},{\"a\":2}]");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("line 2: duplicate key /a"));
+
+    fs::remove_file(input).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_interrupt_closes_array() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jl2js"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+
+    stdin.write_all(b"{\"a\":1}\n").unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+
+    let killed = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    std::thread::sleep(Duration::from_millis(100));
+
+    // The next line is the last one read, though stdin is still open.
+    stdin.write_all(b"{\"a\":2}\n").unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(130));
+    assert_eq!(output.stdout, b"[{\"a\":1},{\"a\":2}]");
+}
+
+#[test]
+fn test_pretty_if_under() {
+    let input = temp_file("pretty-if-under.jsonl", "{\"a\":1}\n{\"a\":2}\n");
+    let convert = |threshold: &str| {
+        Command::new(env!("CARGO_BIN_EXE_jl2js"))
+            .args(["--pretty-if-under", threshold, "--input"])
+            .arg(&input)
+            .output()
+            .unwrap()
+    };
+
+    assert_eq!(
+        String::from_utf8(convert("3").stdout).unwrap(),
+        "[\n{\n  \"a\": 1\n},\n{\n  \"a\": 2\n}\n]"
+    );
+    assert_eq!(convert("2").stdout, b"[{\"a\":1},{\"a\":2}]");
+
+    // Standard input can't be read twice, so it's left compact.
+    let output = Command::new(env!("CARGO_BIN_EXE_jl2js"))
+        .args(["--pretty-if-under", "3"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"[]");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("--pretty-if-under is ignored"));
+
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn test_config() {
+    let dir = std::env::temp_dir().join(format!("jl2js-{}-config", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join(".jl2js.toml"),
+        "format = \"ndjson\"\nlenient = true\npretty = true\n",
+    )
+    .unwrap();
+
+    let convert = |args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_jl2js"))
+            .args(args)
+            .current_dir(&dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(b"{\"a\":1}\noops\n")
+            .unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let output = convert(&[]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"{\n  \"a\": 1\n}\n");
+
+    // The command line wins, even over options it conflicts with.
+    let output = convert(&["--format", "array", "--compact"]);
+    assert_eq!(output.stdout, b"[{\"a\":1}]");
+
+    fs::write(dir.join("other.toml"), "no-such-option = 1\n").unwrap();
+    assert_eq!(convert(&["--config", "other.toml"]).status.code(), Some(3));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_no_clobber() {
+    let input = temp_file("no-clobber.jsonl", "{\"a\":1}\n");
+    let existing = temp_file("no-clobber.json", "original");
+    let args = |extra: &[&str]| {
+        let mut args = vec!["--input", input.to_str().unwrap()];
+        args.extend(["--output", existing.to_str().unwrap()]);
+        args.extend(extra);
+        jl2js(&args)
+    };
+
+    assert_eq!(args(&["--no-clobber"]), Some(2));
+    assert_eq!(fs::read_to_string(&existing).unwrap(), "original");
+
+    assert_eq!(args(&["--no-clobber", "--force"]), Some(0));
+    assert_eq!(fs::read_to_string(&existing).unwrap(), "[{\"a\":1}]");
+
+    fs::remove_file(input).unwrap();
+    fs::remove_file(existing).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_output_to_fifo() {
+    use std::os::unix::fs::FileTypeExt;
+
+    let input = temp_file("fifo.jsonl", "{\"a\":1}\n");
+    let fifo = std::env::temp_dir().join(format!("jl2js-{}-out.fifo", std::process::id()));
+    let _ = fs::remove_file(&fifo);
+    assert!(Command::new("mkfifo")
+        .arg(&fifo)
+        .status()
+        .unwrap()
+        .success());
+
+    // --no-clobber has nothing to protect in a pipe.
+    for extra in [None, Some("--no-clobber")] {
+        let reader = {
+            let fifo = fifo.clone();
+            std::thread::spawn(move || fs::read_to_string(fifo).unwrap())
+        };
+
+        let mut args = vec!["--input", input.to_str().unwrap()];
+        args.extend(["--output", fifo.to_str().unwrap()]);
+        args.extend(extra);
+        assert_eq!(jl2js(&args), Some(0));
+
+        // The output went to whatever reads the pipe, which is still there.
+        assert_eq!(reader.join().unwrap(), "[{\"a\":1}]");
+        assert!(fs::metadata(&fifo).unwrap().file_type().is_fifo());
+    }
+
+    fs::remove_file(input).unwrap();
+    fs::remove_file(fifo).unwrap();
+}
+
+#[test]
+fn test_watch_ignores_own_output() {
+    let dir = std::env::temp_dir().join(format!("jl2js-{}-watch-all", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jl2js"))
+        .args(["--verbose", "--glob", "*", "--watch"])
+        .arg(&dir)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    while !line.contains("watching") {
+        line.clear();
+        assert!(stderr.read_line(&mut line).unwrap() > 0);
+    }
+
+    fs::write(dir.join("events.jsonl"), "{\"a\":1}\n").unwrap();
+
+    let output = dir.join("events.json");
+    let started = Instant::now();
+
+    while !output.exists() && started.elapsed() < Duration::from_secs(10) {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    // Long enough for the output, were it converted, to be converted again.
+    std::thread::sleep(Duration::from_secs(3));
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    let mut log = String::new();
+    std::io::Read::read_to_string(&mut stderr, &mut log).unwrap();
+    assert_eq!(log.matches("converted").count(), 1, "{}", log);
+    assert_eq!(fs::read_to_string(&output).unwrap(), "[{\"a\":1}]");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_lenient_reports_skipped() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jl2js"))
+        .args(["--lenient", "--key-by", "id"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // The second record isn't malformed, but has no id.
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"{\"id\":1}\n{\"x\":2}\noops\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"{\"1\":{\"id\":1}}");
+    assert_eq!(output.stderr, b"skipped 2 invalid records\n");
+}